@@ -5,7 +5,7 @@ use std::process;
 
 fn main() {
     let argv: Vec<String> = env::args().skip(1).collect();
-    if argv.len() < 1 {
+    if argv.is_empty() {
         println!("Must specify command to execute");
         process::exit(1);
     }
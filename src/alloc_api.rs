@@ -0,0 +1,191 @@
+//! The minimal API available on Unix when the `std` feature is disabled.
+//! Programs, arguments and errors are all byte strings, since `OsStr`
+//! needs `std`, and there's no `std::error::Error` impl or conversion to
+//! `io::Error`.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::iter::{IntoIterator, Iterator};
+use core::ptr;
+use errno::errno;
+pub use errno::Errno;
+
+/// Represents an error calling `exec`.
+///
+/// This is the `no_std` version, with only the errors `execvp` itself can
+/// report.  Like the full one, it's marked `#[must_use]`, since it's
+/// returned bare from functions that only return if they fail.
+///
+/// ```
+/// let err = exec::execvp("echo", &["echo", "nul\0byte"]);
+/// assert_eq!(
+///     err,
+///     exec::Error::NullByteInArgument {
+///         index: Some(1),
+///         value: b"nul\0byte".to_vec(),
+///     }
+/// );
+/// assert_eq!(err.to_string(), "argument 1 passed to exec contains an interior NUL byte");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[must_use]
+pub enum Error {
+    /// One of the strings passed to `execvp` contained an internal null
+    /// byte and can't be passed correctly to C.
+    NullByteInArgument {
+        /// The position of the string in `argv`, or `None` if it was the
+        /// program.
+        index: Option<usize>,
+        /// The string itself, as it would have been passed to the system.
+        value: Vec<u8>,
+    },
+    /// An error was returned by the system.
+    Errno(Errno),
+    /// The system's exec function returned without reporting an error.
+    /// This should never happen, since it only returns on failure.
+    Unexpected,
+}
+
+impl Error {
+    /// Create an error from an OS error code, without reading `errno`.
+    ///
+    /// ```
+    /// let err = exec::Error::from_raw_os_error(libc::ENOENT);
+    /// assert_eq!(err.raw_os_error(), Some(libc::ENOENT));
+    /// ```
+    pub fn from_raw_os_error(code: i32) -> Error {
+        Error::Errno(Errno(code))
+    }
+
+    /// Return the OS error code behind this error, if there is one.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match self {
+            Error::Errno(Errno(code)) => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NullByteInArgument {
+                index: Some(index), ..
+            } => write!(
+                f,
+                "argument {} passed to exec contains an interior NUL byte",
+                index
+            ),
+            Error::NullByteInArgument { index: None, value } => write!(
+                f,
+                "{:?} passed to exec contains an interior NUL byte",
+                String::from_utf8_lossy(value)
+            ),
+            Error::Errno(err) => write!(f, "couldn't exec process: {}", err),
+            Error::Unexpected => write!(f, "exec returned without reporting an error"),
+        }
+    }
+}
+
+/// Copy `s` into a new NUL-terminated string.  `index` is its position in
+/// `argv`, for the error if it already contains a NUL.
+fn to_c_string(s: &[u8], index: Option<usize>) -> Result<Vec<u8>, Error> {
+    if s.contains(&0) {
+        return Err(Error::NullByteInArgument {
+            index,
+            value: s.to_vec(),
+        });
+    }
+    let mut c_string = Vec::with_capacity(s.len() + 1);
+    c_string.extend_from_slice(s);
+    c_string.push(0);
+    Ok(c_string)
+}
+
+/// Run `program` with `args`, completely replacing the currently running
+/// program, and searching `PATH` for it if it doesn't contain a slash.  If
+/// it returns at all, it always returns an error.
+///
+/// As with the full version, `program` and the first element of `args`
+/// will normally be identical.
+///
+/// # Examples
+///
+/// ```
+/// let err = exec::execvp("no-such-program", &["no-such-program"]);
+/// assert_eq!(err, exec::Error::from_raw_os_error(libc::ENOENT));
+/// ```
+pub fn execvp<S, I>(program: S, args: I) -> Error
+where
+    S: AsRef<[u8]>,
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    let program = exec_try!(to_c_string(program.as_ref(), None));
+    let mut c_args = Vec::new();
+    for (i, arg) in args.into_iter().enumerate() {
+        c_args.push(exec_try!(to_c_string(arg.as_ref(), Some(i))));
+    }
+    let mut argv: Vec<*const libc::c_char> = c_args
+        .iter()
+        .map(|arg| arg.as_ptr() as *const libc::c_char)
+        .collect();
+    argv.push(ptr::null());
+
+    let res = unsafe { libc::execvp(program.as_ptr() as *const libc::c_char, argv.as_ptr()) };
+    if res < 0 {
+        Error::Errno(errno())
+    } else {
+        // Should never happen, but it's better not to panic if it does.
+        Error::Unexpected
+    }
+}
+
+/// Build a command to execute.  This is the `no_std` version, which only
+/// takes a program and its arguments, and runs it with `execvp`.
+///
+/// ```
+/// let err = exec::Command::new("sh").args(&["-c", "exit 0"]).exec();
+/// panic!("Error: {}", err);
+/// ```
+#[must_use = "a `Command` does nothing unless it is run with `exec`"]
+#[derive(Clone, Debug)]
+pub struct Command {
+    argv: Vec<Vec<u8>>,
+}
+
+impl Command {
+    /// Create a new command builder, specifying the program to run.  The
+    /// program will be searched for using the usual rules for `PATH`.
+    pub fn new<S: AsRef<[u8]>>(program: S) -> Command {
+        Command {
+            argv: alloc::vec![program.as_ref().to_vec()],
+        }
+    }
+
+    /// Add an argument to the command builder.  This can be chained.
+    pub fn arg<S: AsRef<[u8]>>(&mut self, arg: S) -> &mut Command {
+        self.argv.push(arg.as_ref().to_vec());
+        self
+    }
+
+    /// Add multiple arguments to the command builder.  This can be
+    /// chained.
+    pub fn args<I, S>(&mut self, args: I) -> &mut Command
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<[u8]>,
+    {
+        for arg in args {
+            self.arg(arg);
+        }
+        self
+    }
+
+    /// Execute the command we built.  If this function succeeds, it will
+    /// never return.
+    pub fn exec(&mut self) -> Error {
+        execvp(&self.argv[0], &self.argv)
+    }
+}
@@ -0,0 +1,5251 @@
+//! Everything that needs `std`, which is all of the crate unless the
+//! `std` feature is disabled.
+
+#[cfg(unix)]
+use errno;
+use errno::errno;
+pub use errno::Errno;
+use std::borrow::Cow;
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::convert::{Infallible, TryFrom};
+use std::env;
+use std::error;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::io;
+use std::iter::{self, IntoIterator, Iterator};
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Represents an error calling `exec`.
+///
+/// This is marked `#[must_use]`, which is unusual for error types.
+/// Normally, the fact that `Result` is marked in this fashion is
+/// sufficient, but in this case, this error is returned bare from
+/// functions that only return a result if they fail.
+///
+/// Errors can be cloned and compared, which is handy in tests:
+///
+/// ```
+/// let err = exec::execvp("echo", &["echo", "nul\0byte"]);
+/// assert_eq!(
+///     err,
+///     exec::Error::NullByteInArgument {
+///         index: Some(1),
+///         value: "nul\0byte".into(),
+///     }
+/// );
+/// assert_eq!(err.to_string(), "argument 1 passed to exec contains an interior NUL byte");
+/// ```
+#[derive(Debug)]
+#[must_use]
+pub enum Error {
+    /// One of the strings passed to `execv` contained an internal null byte
+    /// and can't be passed correctly to C.
+    NullByteInArgument {
+        /// The position of the string in `argv`, or `None` if it was
+        /// something else, like the program path, an environment variable
+        /// or a directory.
+        index: Option<usize>,
+        /// The string itself, as it would have been passed to the system.
+        /// This is empty if the standard library found the NUL for us, and
+        /// didn't say where.
+        value: OsString,
+    },
+    /// The name of an environment variable passed to exec contained an
+    /// `=` character, which would be misread as the end of the name.
+    InvalidEnvKey,
+    /// An error was returned by the system.  `Errno` is the `errno`
+    /// crate's type, or a local type with the same shape if the
+    /// `errno-crate` feature is disabled.
+    Errno(Errno),
+    /// One of the closures registered with `Command::pre_exec` failed.
+    PreExec(io::Error),
+    /// The program passed to `Command::exec_at` wasn't opened, because
+    /// its name is absolute or leads outside the directory.  The error is
+    /// the one `cap-std` reported.
+    OutsideDir(io::Error),
+    /// The standard library reported an error that has no OS error code,
+    /// such as a failure to spawn or wait for the program on Windows.
+    Io(io::Error),
+    /// The system's exec function returned without reporting an error.
+    /// This should never happen, since it only returns on failure.
+    Unexpected,
+    /// The program was still running when the limit given to
+    /// `Command::exec_timeout` ran out, so it was terminated.
+    TimedOut,
+    /// `Command::strict_env` is on, and this environment variable was set
+    /// to two different values, or both set and removed.
+    EnvConflict(OsString),
+    /// `Command::require_absolute_path` is on, and this program wasn't
+    /// given by an absolute path.
+    RelativePath(OsString),
+    /// We're running under Miri, so everything was checked and converted,
+    /// but the process wasn't changed or replaced.  This is never returned
+    /// otherwise.
+    DryRun,
+}
+
+impl Error {
+    /// Create an error from an OS error code, without reading `errno`.
+    /// This works like `io::Error::from_raw_os_error`, and is handy for
+    /// reporting failures in your own fallback code the same way this
+    /// crate does.
+    ///
+    /// ```
+    /// let err = exec::Error::from_raw_os_error(libc::ENOENT);
+    /// assert_eq!(err.raw_os_error(), Some(libc::ENOENT));
+    /// ```
+    pub fn from_raw_os_error(code: i32) -> Error {
+        Error::Errno(Errno(code))
+    }
+
+    /// Return the OS error code behind this error, if there is one.  This
+    /// works like `io::Error::raw_os_error`.
+    ///
+    /// ```no_run
+    /// let err = exec::execvp("no-such-program", &["no-such-program"]);
+    /// if err.raw_os_error() == Some(libc::ENOENT) {
+    ///     println!("not found");
+    /// }
+    /// ```
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match self {
+            Error::Errno(Errno(code)) => Some(*code),
+            Error::PreExec(err) => err.raw_os_error(),
+            Error::OutsideDir(_) | Error::Io(_) => None,
+            Error::NullByteInArgument { .. }
+            | Error::InvalidEnvKey
+            | Error::Unexpected
+            | Error::TimedOut
+            | Error::EnvConflict(_)
+            | Error::RelativePath(_)
+            | Error::DryRun => None,
+        }
+    }
+
+    /// Return the OS error behind this error as an `Errno`, if there is
+    /// one.  This is the same as `raw_os_error`, just with a different
+    /// type.
+    ///
+    /// ```no_run
+    /// let err = exec::execvp("no-such-program", &["no-such-program"]);
+    /// if let Some(errno) = err.errno() {
+    ///     println!("couldn't exec: {}", errno);
+    /// }
+    /// ```
+    pub fn errno(&self) -> Option<Errno> {
+        self.raw_os_error().map(Errno)
+    }
+
+    /// Was this error caused by an interior NUL byte in an argument?
+    ///
+    /// ```
+    /// let err = exec::execvp("echo", &["echo", "nul\0byte"]);
+    /// assert!(err.is_null_byte());
+    /// assert_eq!(err.errno(), None);
+    /// ```
+    pub fn is_null_byte(&self) -> bool {
+        matches!(self, Error::NullByteInArgument { .. })
+    }
+
+    /// Record that a `NullByteInArgument` error was caused by `argv[index]`.
+    fn at_index(self, index: usize) -> Error {
+        match self {
+            Error::NullByteInArgument { value, .. } => Error::NullByteInArgument {
+                index: Some(index),
+                value,
+            },
+            err => err,
+        }
+    }
+}
+
+/// The `io::Error` from a failed `pre_exec` closure is available as the
+/// `source`, so error reporters can show the whole chain:
+///
+/// ```
+/// use std::error::Error;
+///
+/// let mut cmd = exec::Command::new("true");
+/// unsafe {
+///     cmd.pre_exec(|| Err(std::io::Error::other("not today")));
+/// }
+/// let err = cmd.exec();
+/// assert_eq!(err.source().unwrap().to_string(), "not today");
+/// ```
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::PreExec(err) | Error::OutsideDir(err) | Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// `io::Error` can't be cloned, so `PreExec`, `OutsideDir` and `Io` errors are
+/// cloned by creating a new `io::Error` with the same OS error code, or the
+/// same kind and message.
+impl Clone for Error {
+    fn clone(&self) -> Error {
+        match self {
+            Error::NullByteInArgument { index, value } => Error::NullByteInArgument {
+                index: *index,
+                value: value.clone(),
+            },
+            Error::InvalidEnvKey => Error::InvalidEnvKey,
+            Error::Unexpected => Error::Unexpected,
+            Error::TimedOut => Error::TimedOut,
+            Error::EnvConflict(key) => Error::EnvConflict(key.clone()),
+            Error::RelativePath(program) => Error::RelativePath(program.clone()),
+            Error::DryRun => Error::DryRun,
+            Error::Errno(err) => Error::Errno(*err),
+            Error::PreExec(err) => Error::PreExec(clone_io_error(err)),
+            Error::OutsideDir(err) => Error::OutsideDir(clone_io_error(err)),
+            Error::Io(err) => Error::Io(clone_io_error(err)),
+        }
+    }
+}
+
+/// Two `PreExec`, `OutsideDir` or `Io` errors are equal if they have the same
+/// kind, OS error code and message, since `io::Error` can't be compared
+/// directly.
+impl PartialEq for Error {
+    fn eq(&self, other: &Error) -> bool {
+        match (self, other) {
+            (
+                Error::NullByteInArgument { index, value },
+                Error::NullByteInArgument {
+                    index: other_index,
+                    value: other_value,
+                },
+            ) => index == other_index && value == other_value,
+            (Error::InvalidEnvKey, Error::InvalidEnvKey) => true,
+            (Error::Unexpected, Error::Unexpected) => true,
+            (Error::TimedOut, Error::TimedOut) => true,
+            (Error::EnvConflict(a), Error::EnvConflict(b)) => a == b,
+            (Error::RelativePath(a), Error::RelativePath(b)) => a == b,
+            (Error::DryRun, Error::DryRun) => true,
+            (Error::Errno(a), Error::Errno(b)) => a == b,
+            (Error::PreExec(a), Error::PreExec(b))
+            | (Error::OutsideDir(a), Error::OutsideDir(b))
+            | (Error::Io(a), Error::Io(b)) => {
+                a.kind() == b.kind()
+                    && a.raw_os_error() == b.raw_os_error()
+                    && a.to_string() == b.to_string()
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NullByteInArgument {
+                index: Some(index), ..
+            } => write!(
+                f,
+                "argument {} passed to exec contains an interior NUL byte",
+                index
+            ),
+            Error::NullByteInArgument { index: None, value } => {
+                write!(
+                    f,
+                    "{:?} passed to exec contains an interior NUL byte",
+                    value
+                )
+            }
+            Error::InvalidEnvKey => write!(f, "'=' in environment variable name passed to exec"),
+            Error::Errno(err) => write!(f, "couldn't exec process: {}", err),
+            Error::PreExec(err) => write!(f, "pre_exec closure failed: {}", err),
+            Error::OutsideDir(err) => write!(f, "program is outside the directory: {}", err),
+            Error::Io(err) => write!(f, "couldn't run process: {}", err),
+            Error::Unexpected => write!(f, "exec returned without reporting an error"),
+            Error::TimedOut => write!(f, "program didn't finish before the timeout"),
+            Error::EnvConflict(key) => {
+                write!(f, "conflicting settings for environment variable {:?}", key)
+            }
+            Error::RelativePath(program) => {
+                write!(
+                    f,
+                    "program {:?} passed to exec is not an absolute path",
+                    program
+                )
+            }
+            Error::DryRun => write!(f, "exec skipped under Miri"),
+        }
+    }
+}
+
+impl From<Errno> for Error {
+    fn from(err: Errno) -> Error {
+        Error::Errno(err)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        match err {
+            Error::Errno(Errno(code)) => io::Error::from_raw_os_error(code),
+            Error::PreExec(err) | Error::OutsideDir(err) | Error::Io(err) => err,
+            err @ Error::NullByteInArgument { .. }
+            | err @ Error::InvalidEnvKey
+            | err @ Error::EnvConflict(_)
+            | err @ Error::RelativePath(_) => {
+                io::Error::new(io::ErrorKind::InvalidInput, err.to_string())
+            }
+            err @ Error::Unexpected | err @ Error::DryRun => io::Error::other(err.to_string()),
+            err @ Error::TimedOut => io::Error::new(io::ErrorKind::TimedOut, err.to_string()),
+        }
+    }
+}
+
+/// Why `Command::exec_classified` failed, sorted into the cases a command
+/// line tool usually wants to report differently.
+///
+/// ```
+/// let failure = exec::Command::new("no-such-program").exec_classified();
+/// assert_eq!(failure, exec::ExecFailure::NotFound);
+/// assert_eq!(failure.to_string(), "program not found");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[must_use]
+pub enum ExecFailure {
+    /// The program doesn't exist (`ENOENT`).
+    NotFound,
+    /// We aren't allowed to run the program (`EACCES`).
+    PermissionDenied,
+    /// The program isn't in a format the system can run (`ENOEXEC`).
+    NotExecutable,
+    /// The arguments and environment are too big (`E2BIG`).
+    ArgListTooLong,
+    /// One of the strings passed to exec contained an interior NUL byte.
+    NullByte,
+    /// The system reported some other error.
+    Other(Errno),
+    /// Something other than the exec itself failed, such as a `pre_exec`
+    /// closure or an invalid environment variable name.
+    Failed(Error),
+}
+
+impl fmt::Display for ExecFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecFailure::NotFound => write!(f, "program not found"),
+            ExecFailure::PermissionDenied => write!(f, "permission denied"),
+            ExecFailure::NotExecutable => write!(f, "program isn't in an executable format"),
+            ExecFailure::ArgListTooLong => write!(f, "argument list too long"),
+            ExecFailure::NullByte => write!(f, "interior NUL byte in argument"),
+            ExecFailure::Other(err) => write!(f, "{}", err),
+            ExecFailure::Failed(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for ExecFailure {}
+
+impl From<Error> for ExecFailure {
+    fn from(err: Error) -> ExecFailure {
+        match err {
+            Error::NullByteInArgument { .. } => ExecFailure::NullByte,
+            Error::Errno(Errno(libc::ENOENT)) => ExecFailure::NotFound,
+            Error::Errno(Errno(libc::EACCES)) => ExecFailure::PermissionDenied,
+            Error::Errno(Errno(libc::ENOEXEC)) => ExecFailure::NotExecutable,
+            Error::Errno(Errno(libc::E2BIG)) => ExecFailure::ArgListTooLong,
+            Error::Errno(err) => ExecFailure::Other(err),
+            err => ExecFailure::Failed(err),
+        }
+    }
+}
+
+/// A copy of `err`, which is as close as we can get to cloning it.
+fn clone_io_error(err: &io::Error) -> io::Error {
+    match err.raw_os_error() {
+        Some(code) => io::Error::from_raw_os_error(code),
+        None => io::Error::new(err.kind(), err.to_string()),
+    }
+}
+
+/// Convert an error from one of the `std` wrappers around system calls.
+fn from_io_error(err: io::Error) -> Error {
+    match err.raw_os_error() {
+        Some(code) => Error::Errno(Errno(code)),
+        // `std` doesn't say where the NUL was, only what went wrong, in a
+        // message like "file name contained an unexpected NUL byte" or "nul
+        // byte found in provided data".
+        None if err.kind() == io::ErrorKind::InvalidInput
+            && err.to_string().to_ascii_lowercase().contains("nul byte") =>
+        {
+            Error::NullByteInArgument {
+                index: None,
+                value: OsString::new(),
+            }
+        }
+        None => Error::Io(err),
+    }
+}
+
+/// Check the result of a system call that returns `-1` on failure.
+#[cfg(unix)]
+fn cvt(res: libc::c_int) -> Result<libc::c_int, Error> {
+    if res == -1 {
+        Err(Error::Errno(errno()))
+    } else {
+        Ok(res)
+    }
+}
+
+/// Like `cvt`, but call `f` again for as long as it fails with `EINTR`,
+/// like C's `TEMP_FAILURE_RETRY`, so that a signal arriving while we set
+/// things up doesn't make the whole exec fail.
+#[cfg(unix)]
+fn cvt_r<F: FnMut() -> libc::c_int>(mut f: F) -> Result<libc::c_int, Error> {
+    loop {
+        match cvt(f()) {
+            Err(Error::Errno(Errno(libc::EINTR))) => {}
+            res => return res,
+        }
+    }
+}
+
+/// Close every file descriptor numbered `lowest` or higher, except those
+/// in `keep`.
+#[cfg(unix)]
+fn close_fds_from(lowest: RawFd, keep: &[RawFd]) -> Result<(), Error> {
+    #[cfg(target_os = "linux")]
+    {
+        let close_range = |first: RawFd, last: libc::c_uint| unsafe {
+            libc::syscall(
+                libc::SYS_close_range,
+                first as libc::c_uint,
+                last,
+                0 as libc::c_uint,
+            )
+        };
+        let mut kept: Vec<RawFd> = keep.iter().copied().filter(|&fd| fd >= lowest).collect();
+        kept.sort_unstable();
+        kept.dedup();
+        // Close the ranges between the descriptors we keep.
+        let mut first = lowest;
+        let mut res = 0;
+        for fd in kept {
+            if fd > first {
+                res = close_range(first, (fd - 1) as libc::c_uint);
+                if res != 0 {
+                    break;
+                }
+            }
+            first = fd + 1;
+        }
+        if res == 0 {
+            res = close_range(first, libc::c_uint::MAX);
+        }
+        if res == 0 {
+            return Ok(());
+        }
+        // Older kernels don't have `close_range`.
+    }
+    for_each_fd_from(lowest, |fd| {
+        if keep.contains(&fd) {
+            Ok(())
+        } else {
+            close_fd(fd)
+        }
+    })
+}
+
+/// Call `f` with every file descriptor numbered `lowest` or higher that
+/// might be open.  Where we can find out which descriptors are actually
+/// open, only those are passed.
+#[cfg(unix)]
+fn for_each_fd_from<F>(lowest: RawFd, mut f: F) -> Result<(), Error>
+where
+    F: FnMut(RawFd) -> Result<(), Error>,
+{
+    #[cfg(target_os = "linux")]
+    {
+        if let Ok(entries) = std::fs::read_dir("/proc/self/fd") {
+            let fds: Vec<RawFd> = entries
+                .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+                .collect();
+            for fd in fds.into_iter().filter(|&fd| fd >= lowest) {
+                f(fd)?;
+            }
+            return Ok(());
+        }
+    }
+
+    let max = unsafe { libc::sysconf(libc::_SC_OPEN_MAX) };
+    let max = if max < 0 {
+        1024
+    } else {
+        max.min(RawFd::MAX as libc::c_long) as RawFd
+    };
+    for fd in lowest..max {
+        f(fd)?;
+    }
+    Ok(())
+}
+
+/// Mark every open file descriptor numbered `lowest` or higher
+/// close-on-exec, except those in `keep`, which are made sure not to be.
+#[cfg(unix)]
+fn cloexec_fds_from(lowest: RawFd, keep: &[RawFd]) -> Result<(), Error> {
+    for_each_fd_from(lowest, |fd| {
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        if flags == -1 {
+            let err = errno();
+            // The descriptor isn't open, or was only open while we listed
+            // them.
+            return if err.0 == libc::EBADF {
+                Ok(())
+            } else {
+                Err(Error::Errno(err))
+            };
+        }
+        let new_flags = if keep.contains(&fd) {
+            flags & !libc::FD_CLOEXEC
+        } else {
+            flags | libc::FD_CLOEXEC
+        };
+        if new_flags != flags {
+            cvt_r(|| unsafe { libc::fcntl(fd, libc::F_SETFD, new_flags) })?;
+        }
+        Ok(())
+    })
+}
+
+/// Set or clear the close-on-exec flag on `fd`.
+#[cfg(unix)]
+fn set_cloexec(fd: RawFd, cloexec: bool) -> Result<(), Error> {
+    let flags = cvt(unsafe { libc::fcntl(fd, libc::F_GETFD) })?;
+    let new_flags = if cloexec {
+        flags | libc::FD_CLOEXEC
+    } else {
+        flags & !libc::FD_CLOEXEC
+    };
+    if new_flags != flags {
+        cvt_r(|| unsafe { libc::fcntl(fd, libc::F_SETFD, new_flags) })?;
+    }
+    Ok(())
+}
+
+/// Write all of `buf` to `fd` with raw `write` calls, retrying partial
+/// writes and `EINTR`.
+#[cfg(unix)]
+fn write_all_fd(fd: RawFd, mut buf: &[u8]) -> Result<(), Error> {
+    while !buf.is_empty() {
+        let n = unsafe { libc::write(fd, buf.as_ptr() as *const libc::c_void, buf.len()) };
+        if n == -1 {
+            let err = errno();
+            if err.0 == libc::EINTR {
+                continue;
+            }
+            return Err(Error::Errno(err));
+        }
+        buf = &buf[n as usize..];
+    }
+    Ok(())
+}
+
+/// Close `fd`, ignoring the error if it wasn't open.
+#[cfg(unix)]
+fn close_fd(fd: RawFd) -> Result<(), Error> {
+    if unsafe { libc::close(fd) } == -1 {
+        let err = errno();
+        // After `EINTR`, the descriptor is closed anyway on most systems,
+        // and trying again might close one opened by another thread.
+        if err.0 != libc::EBADF && err.0 != libc::EINTR {
+            return Err(Error::Errno(err));
+        }
+    }
+    Ok(())
+}
+
+/// Give up our controlling terminal, if we have one.
+#[cfg(unix)]
+fn drop_controlling_tty() -> Result<(), Error> {
+    let path = b"/dev/tty\0".as_ptr() as *const libc::c_char;
+    let fd = match cvt_r(|| unsafe {
+        libc::open(path, libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC)
+    }) {
+        Ok(fd) => fd,
+        // `ENXIO` just means there's no terminal to give up.
+        Err(Error::Errno(Errno(libc::ENXIO))) => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    let res = cvt_r(|| unsafe { libc::ioctl(fd, libc::TIOCNOTTY as _) });
+    close_fd(fd)?;
+    res.map(|_| ())
+}
+
+/// One more than the highest signal number, like C's `NSIG`, which `libc`
+/// doesn't export on most platforms.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const NSIG: libc::c_int = 65;
+
+/// One more than the highest signal number, like C's `NSIG`, which `libc`
+/// doesn't export on most platforms.
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+const NSIG: libc::c_int = 33;
+
+/// Reset every signal to its default disposition, and unblock them all.
+#[cfg(unix)]
+fn reset_signals() -> Result<(), Error> {
+    for sig in 1..NSIG {
+        // This fails for signals that can't be changed, like `SIGKILL`, and
+        // for numbers that aren't signals on this system.  Neither matters.
+        unsafe { libc::signal(sig, libc::SIG_DFL) };
+    }
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        cvt(libc::sigemptyset(&mut set))?;
+        match libc::pthread_sigmask(libc::SIG_SETMASK, &set, ptr::null_mut()) {
+            0 => Ok(()),
+            err => Err(Error::Errno(Errno(err))),
+        }
+    }
+}
+
+/// Under Miri, which can't change or replace the process, return
+/// `Error::DryRun` once the strings have been converted, instead of
+/// setting anything up or calling the system.
+macro_rules! dry_run {
+    () => {
+        if cfg!(miri) {
+            return Error::DryRun;
+        }
+    };
+}
+
+/// Run `program` with `args`, completely replacing the currently running
+/// program.  If it returns at all, it always returns an error.
+///
+/// Note that `program` and the first element of `args` will normally be
+/// identical.  The former is the program we ask the operating system to
+/// run, and the latter is the value that will show up in `argv[0]` when
+/// the program executes.  On POSIX systems, these can technically be
+/// completely different, and we've perserved that much of the low-level
+/// API here.
+///
+/// # Examples
+///
+/// ```no_run
+/// let err = exec::execvp("echo", &["echo", "foo"]);
+/// println!("Error: {}", err);
+/// ```
+pub fn execvp<S, I>(program: S, args: I) -> Error
+where
+    S: AsRef<OsStr>,
+    I: IntoIterator,
+    I::Item: AsRef<OsStr>,
+{
+    #[cfg(windows)]
+    let args = args.into_iter().map(|arg| quote_arg(arg.as_ref()));
+    execv_impl(program, args, true, || Ok(()))
+}
+
+/// Run `program` with `args`, completely replacing the currently running
+/// program.  If it returns at all, it always returns an error.
+///
+/// Unlike `execvp`, this does not search `PATH` for `program`.  It is used
+/// verbatim as the path to the executable, so a bare `"echo"` refers to a
+/// file named `echo` in the current directory, and will normally fail with
+/// `ENOENT` instead of finding `/bin/echo`.  Use this when you already know
+/// where the program lives and don't want `PATH` to have any say in it.
+///
+/// # Examples
+///
+/// ```no_run
+/// let err = exec::execv("/bin/echo", &["echo", "foo"]);
+/// println!("Error: {}", err);
+/// ```
+pub fn execv<S, I>(program: S, args: I) -> Error
+where
+    S: AsRef<OsStr>,
+    I: IntoIterator,
+    I::Item: AsRef<OsStr>,
+{
+    #[cfg(windows)]
+    let args = args.into_iter().map(|arg| quote_arg(arg.as_ref()));
+    execv_impl(program, args, false, || Ok(()))
+}
+
+/// Run `program` with `args` and the environment variables in `env`,
+/// completely replacing the currently running program.  If it returns at
+/// all, it always returns an error.
+///
+/// Unlike `execvp`, this does not search `PATH` for `program`, which must
+/// be a path to the executable, and the new program sees only the
+/// variables in `env` rather than inheriting ours.
+///
+/// # Examples
+///
+/// ```no_run
+/// let err = exec::execve("/bin/sh", &["sh", "-c", "echo $GREETING"], vec![("GREETING", "hello")]);
+/// println!("Error: {}", err);
+/// ```
+pub fn execve<S, I, E, K, V>(program: S, args: I, env: E) -> Error
+where
+    S: AsRef<OsStr>,
+    I: IntoIterator,
+    I::Item: AsRef<OsStr>,
+    E: IntoIterator<Item = (K, V)>,
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+{
+    #[cfg(windows)]
+    let args = args.into_iter().map(|arg| quote_arg(arg.as_ref()));
+    execve_impl(program, args, env, false, || Ok(()))
+}
+
+/// Like `execvp`, but takes arguments that are already C strings, so that
+/// nothing has to be converted before running the program.  This is a
+/// fast path for callers that exec repeatedly and keep their `CString`s
+/// around.  If it returns at all, it always returns an error.
+///
+/// This is only available on Unix.
+///
+/// # Examples
+///
+/// ```
+/// use std::ffi::CStr;
+///
+/// let sh = CStr::from_bytes_with_nul(b"sh\0").unwrap();
+/// let c = CStr::from_bytes_with_nul(b"-c\0").unwrap();
+/// let exit = CStr::from_bytes_with_nul(b"exit 0\0").unwrap();
+/// let err = exec::execvp_cstr(sh, &[sh, c, exit]);
+/// panic!("Error: {}", err);
+/// ```
+#[cfg(unix)]
+pub fn execvp_cstr(program: &std::ffi::CStr, args: &[&std::ffi::CStr]) -> Error {
+    let mut arg_charptrs: Vec<_> = args.iter().map(|arg| arg.as_ptr()).collect();
+    arg_charptrs.push(ptr::null());
+    sys_exec(&Libc, program, &arg_charptrs, None, true)
+}
+
+/// Run the program at the path `program` with the arguments `argv` and, if
+/// it's given, exactly the environment `envp`, without allocating any
+/// memory or taking any locks.  If it returns at all, it always returns an
+/// error.
+///
+/// This is only available on Unix.  It's meant for the child of a `fork`
+/// in a multithreaded program, where only async-signal-safe functions may
+/// be called, since the allocator's lock may be held by a thread that no
+/// longer exists.  Everything has to be prepared before the `fork`:
+/// `argv` and `envp` can come from `Command::build_c_argv` and
+/// `Command::build_c_envp`, and the program's path from
+/// `Command::get_resolved_program`, since `PATH` isn't searched here.
+/// Only `execv` or `execve` is called, and the error just records
+/// `errno`.
+///
+/// # Examples
+///
+/// ```
+/// use std::ffi::CString;
+///
+/// // Before the fork.
+/// let mut cmd = exec::Command::new("sh");
+/// cmd.args(&["-c", "test \"$GREETING\" = hello"]).env("GREETING", "hello");
+/// let program = cmd.get_resolved_program().unwrap();
+/// let program = CString::new(program.into_os_string().into_string().unwrap()).unwrap();
+/// let argv = cmd.build_c_argv().unwrap();
+/// let envp = cmd.build_c_envp().unwrap();
+///
+/// // After the fork, only this.
+/// let err = exec::exec_signal_safe(&program, &argv, envp.as_ref());
+/// panic!("Error: {}", err);
+/// ```
+#[cfg(unix)]
+pub fn exec_signal_safe(
+    program: &std::ffi::CStr,
+    argv: &CStringArray,
+    envp: Option<&CStringArray>,
+) -> Error {
+    sys_exec(
+        &Libc,
+        program,
+        argv.as_slice(),
+        envp.map(CStringArray::as_slice),
+        false,
+    )
+}
+
+/// Run the executable open as `fd` with `args` and the environment
+/// variables in `env`, completely replacing the currently running program.
+/// If it returns at all, it always returns an error.
+///
+/// Because the program is identified by an open file rather than a path,
+/// it can't be swapped out by someone else between the time we check it
+/// and the time we run it.  This is only available on platforms that have
+/// `fexecve`, including Linux and FreeBSD.
+///
+/// On Linux, `fd` can be opened with `O_PATH` or as an ordinary readable
+/// file.  If the kernel doesn't support `execveat`, glibc emulates this by
+/// running `/proc/self/fd/<fd>`, which only works when `/proc` is mounted.
+/// Scripts that start with `#!` can only be run this way if `fd` is not
+/// close-on-exec, because the interpreter has to open the script by name.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::os::unix::io::AsRawFd;
+///
+/// let file = File::open("/bin/echo").unwrap();
+/// // ... check that `file` is the program we expect ...
+/// let err = exec::fexecve(file.as_raw_fd(), &["echo", "hello"], vec![("LANG", "C")]);
+/// println!("Error: {}", err);
+/// ```
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+))]
+pub fn fexecve<I, E, K, V>(fd: RawFd, args: I, env: E) -> Error
+where
+    I: IntoIterator,
+    I::Item: AsRef<OsStr>,
+    E: IntoIterator<Item = (K, V)>,
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+{
+    let argv = exec_try!(CStringArray::from_args(args));
+    let envp = exec_try!(CStringArray::from_env(env));
+    dry_run!();
+    sys_fexecve(fd, &argv, &envp)
+}
+
+/// Call `fexecve` with arrays that have already been converted, and turn
+/// the result into an `Error`.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+))]
+fn sys_fexecve(fd: RawFd, argv: &CStringArray, envp: &CStringArray) -> Error {
+    let res = unsafe { libc::fexecve(fd, argv.as_ptr(), envp.as_ptr()) };
+
+    // Handle our error result.
+    if res < 0 {
+        Error::Errno(errno())
+    } else {
+        // Should never happen, but it's better not to panic if it does.
+        Error::Unexpected
+    }
+}
+
+/// Find the executable that `execvp` would run for `program`, without
+/// running it.  Returns `None` if there isn't one.
+///
+/// This follows the same rules as `execvp`: if `program` contains a path
+/// separator it's used as is, and otherwise each directory in `PATH` is
+/// tried in turn, with an empty entry meaning the current directory.  On
+/// Unix, a candidate must be a file we have permission to execute.  On
+/// Windows, the current directory is searched before `PATH`, and if
+/// `program` has no extension, each extension in `PATHEXT` is tried.
+///
+/// ```no_run
+/// match exec::resolve_in_path("echo") {
+///     Some(path) => println!("echo is {}", path.display()),
+///     None => println!("echo not found"),
+/// }
+/// ```
+pub fn resolve_in_path<S: AsRef<OsStr>>(program: S) -> Option<PathBuf> {
+    resolve_in(program.as_ref(), env::var_os("PATH").as_deref())
+}
+
+/// Build a `Command` that runs a fresh copy of the current executable,
+/// with the same `argv[0]` we were started with, but no arguments, so that
+/// a program can re-exec itself after adjusting its arguments or
+/// environment.
+///
+/// The path comes from `std::env::current_exe`, so it has the same
+/// caveats: on some platforms it's based on `argv[0]` or a path that may
+/// be out of date if the file has been moved or replaced since we
+/// started, and it may be a symlink.  It's always absolute, though, so
+/// `PATH` won't be searched.
+///
+/// ```
+/// // The fresh copy sees the variable and stops here.
+/// if std::env::var_os("EXEC_DOCTEST_REEXECED").is_some() {
+///     return;
+/// }
+/// let err = exec::current_exe_command()
+///     .unwrap()
+///     .forward_args()
+///     .env("EXEC_DOCTEST_REEXECED", "1")
+///     .exec();
+/// panic!("Error: {}", err);
+/// ```
+pub fn current_exe_command() -> io::Result<Command> {
+    let mut cmd = Command::new(env::current_exe()?);
+    if let Some(arg0) = env::args_os().next() {
+        cmd.arg0(arg0);
+    }
+    Ok(cmd)
+}
+
+/// Run the first of `candidates` that can be run, trying each in turn and
+/// moving on to the next only while they fail because the program wasn't
+/// found (`ENOENT`) or couldn't be executed (`EACCES`).  Any other error
+/// is returned straight away, and if every candidate fails, the last
+/// error is returned.  If this function succeeds, it will never return.
+///
+/// Each attempt applies that command's settings to the current process
+/// before the exec, and they aren't undone if it fails, so the later
+/// candidates should usually differ only in the program.  Failing to
+/// apply a setting, such as a missing `current_dir`, can also report
+/// `ENOENT` and move on.  With no candidates at all, this returns
+/// `ENOENT`.
+///
+/// ```
+/// let err = exec::exec_first(&[
+///     exec::Command::new("/nonexistent/bin/tool"),
+///     exec::Command::with_args("/bin/sh", &["-c", "exit 0"]),
+/// ]);
+/// panic!("Error: {}", err);
+/// ```
+pub fn exec_first(candidates: &[Command]) -> Error {
+    let mut err = Error::Errno(Errno(libc::ENOENT));
+    for cmd in candidates {
+        err = cmd.exec_ref();
+        match err.raw_os_error() {
+            Some(libc::ENOENT) | Some(libc::EACCES) => {}
+            _ => break,
+        }
+    }
+    err
+}
+
+/// How much of a script's first line Linux looks at for its `#!` line:
+/// one less than its traditional `BINPRM_BUF_SIZE`.
+#[cfg(unix)]
+const SHEBANG_MAX: u64 = 127;
+
+/// Find the interpreter the kernel would use to run the script at `path`,
+/// and the argument it would pass before the script's name, if any.
+/// Returns `None` if the file doesn't start with `#!`, or names no
+/// interpreter.
+///
+/// This is only available on Unix.  The line is parsed the way Linux
+/// does it: only its first 127 bytes are looked at, the interpreter ends
+/// at the first space or tab, and everything after that, less surrounding
+/// whitespace, is passed as a single argument, spaces and all.  Other
+/// systems may split the argument or look further, and the interpreter
+/// isn't checked to exist, so this is best used as a preflight check.
+///
+/// ```
+/// use std::ffi::OsString;
+/// use std::path::PathBuf;
+///
+/// let script = std::env::temp_dir().join("exec-shebang-doctest.sh");
+/// std::fs::write(&script, "#! /usr/bin/env  python3 -u \nprint('hi')\n").unwrap();
+/// assert_eq!(
+///     exec::resolve_shebang(&script).unwrap(),
+///     Some((PathBuf::from("/usr/bin/env"), Some(OsString::from("python3 -u")))),
+/// );
+///
+/// std::fs::write(&script, "echo hi\n").unwrap();
+/// assert_eq!(exec::resolve_shebang(&script).unwrap(), None);
+/// ```
+#[cfg(unix)]
+pub fn resolve_shebang<P: AsRef<Path>>(path: P) -> io::Result<Option<(PathBuf, Option<OsString>)>> {
+    use std::io::Read;
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut buf = vec![];
+    std::fs::File::open(path)?
+        .take(SHEBANG_MAX)
+        .read_to_end(&mut buf)?;
+    if !buf.starts_with(b"#!") {
+        return Ok(None);
+    }
+    // The kernel treats the line as a C string.
+    let line = buf[2..]
+        .split(|&b| b == b'\n' || b == 0)
+        .next()
+        .unwrap_or(&[]);
+    let is_blank = |b: &u8| *b == b' ' || *b == b'\t';
+    let start = line.iter().position(|b| !is_blank(b));
+    let end = line.iter().rposition(|b| !is_blank(b));
+    let line = match (start, end) {
+        (Some(start), Some(end)) => &line[start..=end],
+        _ => return Ok(None),
+    };
+    let (interpreter, arg) = match line.iter().position(is_blank) {
+        Some(i) => {
+            let rest = &line[i..];
+            let arg = &rest[rest.iter().position(|b| !is_blank(b)).unwrap_or(0)..];
+            (&line[..i], Some(OsStr::from_bytes(arg).to_owned()))
+        }
+        None => (line, None),
+    };
+    Ok(Some((PathBuf::from(OsStr::from_bytes(interpreter)), arg)))
+}
+
+/// Search the directories in `path` for `program`, or the system's default
+/// search path if `path` is `None`.
+fn resolve_in(program: &OsStr, path: Option<&OsStr>) -> Option<PathBuf> {
+    let program = Path::new(program);
+    if program.as_os_str().is_empty() {
+        return None;
+    }
+    if program.components().count() > 1 || program.has_root() {
+        return find_executable(program);
+    }
+
+    let path = path.unwrap_or_else(|| OsStr::new(DEFAULT_PATH));
+    search_dirs(path)
+        .into_iter()
+        .filter_map(|dir| find_executable(&dir.join(program)))
+        .next()
+}
+
+/// The directories to search for a program, in order.  An empty `PATH`
+/// entry means the current directory.
+#[cfg(unix)]
+fn search_dirs(path: &OsStr) -> Vec<PathBuf> {
+    env::split_paths(path)
+        .map(|dir| {
+            if dir.as_os_str().is_empty() {
+                PathBuf::from(".")
+            } else {
+                dir
+            }
+        })
+        .collect()
+}
+
+/// The directories to search for a program, in order.  The current
+/// directory always comes first, and empty `PATH` entries are ignored.
+#[cfg(windows)]
+fn search_dirs(path: &OsStr) -> Vec<PathBuf> {
+    iter::once(PathBuf::from("."))
+        .chain(env::split_paths(path).filter(|dir| !dir.as_os_str().is_empty()))
+        .collect()
+}
+
+/// The search path `execvp` uses when `PATH` isn't set.
+#[cfg(unix)]
+const DEFAULT_PATH: &str = "/bin:/usr/bin";
+
+/// The search path `execvp` uses when `PATH` isn't set.
+#[cfg(windows)]
+const DEFAULT_PATH: &str = "";
+
+/// Return `path` if it's something we can execute.
+#[cfg(unix)]
+fn find_executable(path: &Path) -> Option<PathBuf> {
+    let is_file = path.metadata().map(|meta| meta.is_file()).unwrap_or(false);
+    let cpath = to_cstring(path.as_os_str()).ok()?;
+    if is_file && unsafe { libc::access(cpath.as_ptr(), libc::X_OK) } == 0 {
+        Some(path.to_owned())
+    } else {
+        None
+    }
+}
+
+/// Return `path` if it's something we can execute, trying each extension
+/// in `PATHEXT` if it doesn't already have one.
+#[cfg(windows)]
+fn find_executable(path: &Path) -> Option<PathBuf> {
+    if path.extension().is_some() {
+        return if path.is_file() {
+            Some(path.to_owned())
+        } else {
+            None
+        };
+    }
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+
+    let pathext = env::var_os("PATHEXT").unwrap_or_else(|| OsString::from(".COM;.EXE;.BAT;.CMD"));
+    // Split the wide string itself, so that nothing that isn't valid
+    // Unicode gets replaced along the way.
+    let pathext: Vec<u16> = pathext.encode_wide().collect();
+    pathext
+        .split(|&x| x == u16::from(b';'))
+        .filter(|ext| !ext.is_empty())
+        .map(|ext| {
+            let mut candidate = path.as_os_str().to_owned();
+            candidate.push(OsString::from_wide(ext));
+            PathBuf::from(candidate)
+        })
+        .find(|candidate| candidate.is_file())
+}
+
+/// Run `program` with `args` and our environment.  `PATH` is only searched
+/// for `program` if `search_path` is true.  Once all the arguments have
+/// been converted, `setup` is called just before the exec itself.
+#[cfg(unix)]
+fn execv_impl<S, I, F>(program: S, args: I, search_path: bool, setup: F) -> Error
+where
+    S: AsRef<OsStr>,
+    I: IntoIterator,
+    I::Item: AsRef<OsStr>,
+    F: FnOnce() -> Result<(), Error>,
+{
+    // Add null terminations to our strings and our argument array,
+    // converting them into a C-compatible format.
+    let program_cstring = exec_try!(to_cstring(program.as_ref()));
+    let argv = exec_try!(CStringArray::from_args(args));
+    dry_run!();
+    exec_try!(setup());
+
+    sys_exec(&Libc, &program_cstring, argv.as_slice(), None, search_path)
+}
+
+/// Like `execv_impl`, but the new program gets exactly the environment
+/// in `env` instead of inheriting ours.
+#[cfg(unix)]
+fn execve_impl<S, I, E, K, V, F>(program: S, args: I, env: E, search_path: bool, setup: F) -> Error
+where
+    S: AsRef<OsStr>,
+    I: IntoIterator,
+    I::Item: AsRef<OsStr>,
+    E: IntoIterator<Item = (K, V)>,
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+    F: FnOnce() -> Result<(), Error>,
+{
+    let program_cstring = exec_try!(to_cstring(program.as_ref()));
+    let argv = exec_try!(CStringArray::from_args(args));
+    let envp = exec_try!(CStringArray::from_env(env));
+    dry_run!();
+    exec_try!(setup());
+
+    sys_exec(
+        &Libc,
+        &program_cstring,
+        argv.as_slice(),
+        Some(envp.as_slice()),
+        search_path,
+    )
+}
+
+/// The system calls that actually run a program.  The exec functions go
+/// through this instead of calling libc directly, so that the way their
+/// results are handled doesn't depend on really exec'ing anything.
+#[cfg(unix)]
+trait Syscalls {
+    /// Run `program` with the NULL-terminated `argv`, and `envp` if it's
+    /// given or our environment otherwise, searching `PATH` for it if
+    /// `search_path` is true.  This only returns on failure.
+    unsafe fn exec(
+        &self,
+        program: &std::ffi::CStr,
+        argv: *const *const libc::c_char,
+        envp: Option<*const *const libc::c_char>,
+        search_path: bool,
+    ) -> libc::c_int;
+
+    /// The error code left behind by the last failed call.
+    fn errno(&self) -> Errno;
+}
+
+/// The real system calls.
+#[cfg(unix)]
+struct Libc;
+
+#[cfg(unix)]
+impl Syscalls for Libc {
+    unsafe fn exec(
+        &self,
+        program: &std::ffi::CStr,
+        argv: *const *const libc::c_char,
+        envp: Option<*const *const libc::c_char>,
+        search_path: bool,
+    ) -> libc::c_int {
+        match (envp, search_path) {
+            (None, true) => libc::execvp(program.as_ptr(), argv),
+            (None, false) => libc::execv(program.as_ptr(), argv),
+            (Some(envp), true) => sys_execvpe(program, argv, envp),
+            (Some(envp), false) => libc::execve(program.as_ptr(), argv, envp),
+        }
+    }
+
+    fn errno(&self) -> Errno {
+        errno()
+    }
+}
+
+/// Run `program` using `sys`, and turn the result into an `Error`.  The
+/// `argv` and `envp` arrays must already be NULL-terminated.
+#[cfg(unix)]
+fn sys_exec<Sys: Syscalls>(
+    sys: &Sys,
+    program: &std::ffi::CStr,
+    argv: &[*const libc::c_char],
+    envp: Option<&[*const libc::c_char]>,
+    search_path: bool,
+) -> Error {
+    dry_run!();
+    let res = unsafe {
+        sys.exec(
+            program,
+            argv.as_ptr(),
+            envp.map(|envp| envp.as_ptr()),
+            search_path,
+        )
+    };
+
+    // Handle our error result.
+    if res < 0 {
+        Error::Errno(sys.errno())
+    } else {
+        // Should never happen, but it's better not to panic if it does.
+        Error::Unexpected
+    }
+}
+
+/// Convert `s` to a NUL-terminated C string.
+#[cfg(unix)]
+fn to_cstring(s: &OsStr) -> Result<std::ffi::CString, Error> {
+    use std::os::unix::ffi::OsStrExt;
+
+    std::ffi::CString::new(s.as_bytes()).map_err(|_| null_byte(s))
+}
+
+/// The error for a string `s` that contains a NUL byte.  If it's one of
+/// the arguments, the caller adds its index with `Error::at_index`.
+fn null_byte<S: AsRef<OsStr>>(s: S) -> Error {
+    Error::NullByteInArgument {
+        index: None,
+        value: s.as_ref().to_owned(),
+    }
+}
+
+/// Convert an environment variable to the `KEY=VALUE` C string stored in
+/// `envp`.
+#[cfg(unix)]
+fn env_cstring(key: &OsStr, val: &OsStr) -> Result<std::ffi::CString, Error> {
+    use std::os::unix::ffi::OsStrExt;
+
+    if key.as_bytes().contains(&b'=') {
+        return Err(Error::InvalidEnvKey);
+    }
+    let mut var = key.to_owned();
+    var.push("=");
+    var.push(val);
+    to_cstring(&var)
+}
+
+/// Search `PATH` for `program` like `execvp`, but run it with the
+/// environment `envp`.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+unsafe fn sys_execvpe(
+    program: &std::ffi::CStr,
+    argv: *const *const libc::c_char,
+    envp: *const *const libc::c_char,
+) -> libc::c_int {
+    libc::execvpe(program.as_ptr(), argv, envp)
+}
+
+/// Search `PATH` for `program` like `execvp`, but run it with the
+/// environment `envp`.
+///
+/// This platform's libc has no `execvpe`, so we walk `PATH` ourselves
+/// using the same rules as `execvp`: a name containing a `/` is used as
+/// is, an empty `PATH` entry means the current directory, and `EACCES`
+/// from any candidate is reported if nothing better turns up.
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    ))
+))]
+unsafe fn sys_execvpe(
+    program: &std::ffi::CStr,
+    argv: *const *const libc::c_char,
+    envp: *const *const libc::c_char,
+) -> libc::c_int {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let name = program.to_bytes();
+    if name.is_empty() {
+        errno::set_errno(Errno(libc::ENOENT));
+        return -1;
+    }
+    if name.contains(&b'/') {
+        return libc::execve(program.as_ptr(), argv, envp);
+    }
+
+    let path = env::var_os("PATH").unwrap_or_else(|| OsString::from(DEFAULT_PATH));
+    let mut saw_eacces = false;
+    for dir in path.as_bytes().split(|&b| b == b':') {
+        let mut candidate = if dir.is_empty() {
+            b".".to_vec()
+        } else {
+            dir.to_vec()
+        };
+        candidate.push(b'/');
+        candidate.extend_from_slice(name);
+        let candidate = match CString::new(candidate) {
+            Ok(candidate) => candidate,
+            Err(_) => continue,
+        };
+        libc::execve(candidate.as_ptr(), argv, envp);
+        match errno().0 {
+            libc::EACCES => saw_eacces = true,
+            libc::ENOENT | libc::ENOTDIR => {}
+            _ => return -1,
+        }
+    }
+    if saw_eacces {
+        errno::set_errno(Errno(libc::EACCES));
+    }
+    -1
+}
+
+/// Run `program` with `args` and our environment.  `PATH` is only searched
+/// for `program` if `search_path` is true.  Once all the arguments have
+/// been converted, `setup` is called just before the exec itself.
+#[cfg(windows)]
+fn execv_impl<S, I, F>(program: S, args: I, search_path: bool, setup: F) -> Error
+where
+    S: AsRef<OsStr>,
+    I: IntoIterator,
+    I::Item: AsRef<OsStr>,
+    F: FnOnce() -> Result<(), Error>,
+{
+    let program_wide = exec_try!(to_wide(program.as_ref()));
+    let args_wide = exec_try!(args
+        .into_iter()
+        .enumerate()
+        .map(|(i, arg)| to_wide(arg.as_ref()).map_err(|err| err.at_index(i)))
+        .collect::<Result<Vec<_>, _>>());
+    let mut arg_ptrs: Vec<_> = args_wide.iter().map(|arg| arg.as_ptr()).collect();
+    arg_ptrs.push(ptr::null());
+    dry_run!();
+    exec_try!(setup());
+
+    let res = unsafe {
+        if search_path {
+            libc::wexecvp(program_wide.as_ptr(), arg_ptrs.as_ptr())
+        } else {
+            libc::wexecv(program_wide.as_ptr(), arg_ptrs.as_ptr())
+        }
+    };
+
+    // Handle our error result.
+    if res < 0 {
+        Error::Errno(errno())
+    } else {
+        // Should never happen, but it's better not to panic if it does.
+        Error::Unexpected
+    }
+}
+
+/// Like `execv_impl`, but the new program gets exactly the environment
+/// in `env` instead of inheriting ours.
+#[cfg(windows)]
+fn execve_impl<S, I, E, K, V, F>(program: S, args: I, env: E, search_path: bool, setup: F) -> Error
+where
+    S: AsRef<OsStr>,
+    I: IntoIterator,
+    I::Item: AsRef<OsStr>,
+    E: IntoIterator<Item = (K, V)>,
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+    F: FnOnce() -> Result<(), Error>,
+{
+    let program_wide = exec_try!(to_wide(program.as_ref()));
+    let args_wide = exec_try!(args
+        .into_iter()
+        .enumerate()
+        .map(|(i, arg)| to_wide(arg.as_ref()).map_err(|err| err.at_index(i)))
+        .collect::<Result<Vec<_>, _>>());
+    let env_wide = exec_try!(env
+        .into_iter()
+        .map(|(key, val)| env_wide(key.as_ref(), val.as_ref()))
+        .collect::<Result<Vec<_>, _>>());
+    let mut arg_ptrs: Vec<_> = args_wide.iter().map(|arg| arg.as_ptr()).collect();
+    arg_ptrs.push(ptr::null());
+    let mut env_ptrs: Vec<_> = env_wide.iter().map(|var| var.as_ptr()).collect();
+    env_ptrs.push(ptr::null());
+    dry_run!();
+    exec_try!(setup());
+
+    let res = unsafe {
+        if search_path {
+            libc::wexecvpe(program_wide.as_ptr(), arg_ptrs.as_ptr(), env_ptrs.as_ptr())
+        } else {
+            libc::wexecve(program_wide.as_ptr(), arg_ptrs.as_ptr(), env_ptrs.as_ptr())
+        }
+    };
+
+    // Handle our error result.
+    if res < 0 {
+        Error::Errno(errno())
+    } else {
+        // Should never happen, but it's better not to panic if it does.
+        Error::Unexpected
+    }
+}
+
+/// Run `program` with `args`, and either `env` or, if that's `None`, our
+/// own environment.
+fn exec_env<S, I, F>(
+    program: S,
+    args: I,
+    env: Option<&Vec<(OsString, OsString)>>,
+    search_path: bool,
+    setup: F,
+) -> Error
+where
+    S: AsRef<OsStr>,
+    I: IntoIterator,
+    I::Item: AsRef<OsStr>,
+    F: FnOnce() -> Result<(), Error>,
+{
+    match env {
+        None => execv_impl(program, args, search_path, setup),
+        Some(env) => execve_impl(
+            program,
+            args,
+            env.iter().map(|(key, val)| (key, val)),
+            search_path,
+            setup,
+        ),
+    }
+}
+
+/// The shell used to run scripts that don't name an interpreter.
+#[cfg(unix)]
+const SHELL: &str = "/bin/sh";
+
+/// The environment variables that change how the dynamic linker loads a
+/// program, as removed by `Command::scrub_linker_env`.
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "ios"))))]
+const LINKER_ENV: &[&str] = &[
+    "GLIBC_TUNABLES",
+    "LD_32_LIBRARY_PATH",
+    "LD_32_PRELOAD",
+    "LD_ASSUME_KERNEL",
+    "LD_AUDIT",
+    "LD_BIND_NOT",
+    "LD_BIND_NOW",
+    "LD_DEBUG",
+    "LD_DEBUG_OUTPUT",
+    "LD_DYNAMIC_WEAK",
+    "LD_ELF_HINTS_PATH",
+    "LD_HWCAP_MASK",
+    "LD_LIBMAP",
+    "LD_LIBMAP_DISABLE",
+    "LD_LIBRARY_PATH",
+    "LD_LIBRARY_PATH_FDS",
+    "LD_LIBRARY_PATH_RPATH",
+    "LD_ORIGIN_PATH",
+    "LD_POINTER_GUARD",
+    "LD_PRELOAD",
+    "LD_PRELOAD_FDS",
+    "LD_PROFILE",
+    "LD_PROFILE_OUTPUT",
+    "LD_SHOW_AUXV",
+    "LD_TRACE_LOADED_OBJECTS",
+    "LD_USE_LOAD_BIAS",
+    "LD_VERBOSE",
+    "LD_WARN",
+];
+
+/// The environment variables that change how the dynamic linker loads a
+/// program, as removed by `Command::scrub_linker_env`.
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+const LINKER_ENV: &[&str] = &[
+    "DYLD_BIND_AT_LAUNCH",
+    "DYLD_FALLBACK_FRAMEWORK_PATH",
+    "DYLD_FALLBACK_LIBRARY_PATH",
+    "DYLD_FORCE_FLAT_NAMESPACE",
+    "DYLD_FRAMEWORK_PATH",
+    "DYLD_IMAGE_SUFFIX",
+    "DYLD_INSERT_LIBRARIES",
+    "DYLD_LIBRARY_PATH",
+    "DYLD_NO_FIX_PREBINDING",
+    "DYLD_PRINT_TO_FILE",
+    "DYLD_ROOT_PATH",
+    "DYLD_SHARED_CACHE_DIR",
+    "DYLD_SHARED_REGION",
+    "DYLD_VERSIONED_FRAMEWORK_PATH",
+    "DYLD_VERSIONED_LIBRARY_PATH",
+];
+
+/// Convert `s` to a NUL-terminated wide string.
+#[cfg(windows)]
+fn to_wide(s: &OsStr) -> Result<Vec<u16>, Error> {
+    use std::os::windows::ffi::OsStrExt;
+
+    let mut vec: Vec<u16> = s.encode_wide().collect();
+    if vec.contains(&0) {
+        // We have an interior null.
+        // The Unix impl includes a NulError, but that's only constructible using CString.
+        Err(null_byte(s))
+    } else {
+        vec.push(0); // append null terminator
+        Ok(vec)
+    }
+}
+
+/// Quote `arg` so that it survives being split back out of the command
+/// line.
+///
+/// The C runtime's `exec` functions just join their arguments with spaces
+/// to build the command line, so we have to do the quoting ourselves,
+/// following the same rules as the runtime's startup code (and
+/// `std::process::Command`): backslashes are only special right before a
+/// quote, so any backslashes there are doubled, and the quote itself is
+/// escaped.  This works on the UTF-16 units directly, so unpaired
+/// surrogates come through unchanged.
+#[cfg(windows)]
+fn quote_arg(arg: &OsStr) -> OsString {
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+
+    const QUOTE: u16 = b'"' as u16;
+    const BACKSLASH: u16 = b'\\' as u16;
+
+    let quote = arg.is_empty()
+        || arg
+            .encode_wide()
+            .any(|x| x == u16::from(b' ') || x == u16::from(b'\t'));
+    let mut vec = vec![];
+    if quote {
+        vec.push(QUOTE);
+    }
+    let mut backslashes = 0;
+    for x in arg.encode_wide() {
+        if x == BACKSLASH {
+            backslashes += 1;
+        } else {
+            if x == QUOTE {
+                // Double the backslashes, and escape the quote.
+                vec.extend(iter::repeat_n(BACKSLASH, backslashes + 1));
+            }
+            backslashes = 0;
+        }
+        vec.push(x);
+    }
+    if quote {
+        // Our closing quote mustn't be escaped by trailing backslashes.
+        vec.extend(iter::repeat_n(BACKSLASH, backslashes));
+        vec.push(QUOTE);
+    }
+    OsString::from_wide(&vec)
+}
+
+/// Convert an environment variable to the `KEY=VALUE` wide string stored
+/// in `envp`.
+#[cfg(windows)]
+fn env_wide(key: &OsStr, val: &OsStr) -> Result<Vec<u16>, Error> {
+    use std::os::windows::ffi::OsStrExt;
+
+    if key.encode_wide().any(|x| x == u16::from(b'=')) {
+        return Err(Error::InvalidEnvKey);
+    }
+    let mut var = key.to_owned();
+    var.push("=");
+    var.push(val);
+    to_wide(&var)
+}
+
+/// Environment variable names are case-insensitive on Windows.
+#[cfg(windows)]
+fn same_env_key(a: &OsStr, b: &OsStr) -> bool {
+    a.eq_ignore_ascii_case(b)
+}
+
+#[cfg(not(windows))]
+fn same_env_key(a: &OsStr, b: &OsStr) -> bool {
+    a == b
+}
+
+/// Replace each `$NAME` or `${NAME}` in `arg` with what `lookup` returns
+/// for `NAME`, or with nothing if it returns `None`.  A name is a letter or
+/// underscore followed by letters, digits and underscores, and a `$` that
+/// isn't followed by one is kept.
+fn expand_vars<F>(arg: &OsStr, lookup: F) -> OsString
+where
+    F: Fn(&OsStr) -> Option<OsString>,
+{
+    fn name_len(bytes: &[u8]) -> usize {
+        match bytes.first() {
+            Some(b) if b.is_ascii_alphabetic() || *b == b'_' => bytes
+                .iter()
+                .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_')
+                .count(),
+            _ => 0,
+        }
+    }
+    // We only ever split the string next to ASCII characters, which keeps
+    // every piece a valid `OsStr`.
+    fn os_str(bytes: &[u8]) -> &OsStr {
+        unsafe { OsStr::from_encoded_bytes_unchecked(bytes) }
+    }
+
+    let bytes = arg.as_encoded_bytes();
+    let mut expanded = OsString::new();
+    // Everything before `copied` is already in `expanded`.
+    let mut copied = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'$' {
+            i += 1;
+            continue;
+        }
+        let rest = &bytes[i + 1..];
+        let (name, end) = if rest.first() == Some(&b'{') {
+            let len = name_len(&rest[1..]);
+            if len == 0 || rest.get(len + 1) != Some(&b'}') {
+                i += 1;
+                continue;
+            }
+            (&rest[1..len + 1], i + len + 3)
+        } else {
+            let len = name_len(rest);
+            if len == 0 {
+                i += 1;
+                continue;
+            }
+            (&rest[..len], i + len + 1)
+        };
+        expanded.push(os_str(&bytes[copied..i]));
+        if let Some(val) = lookup(os_str(name)) {
+            expanded.push(val);
+        }
+        copied = end;
+        i = end;
+    }
+    expanded.push(os_str(&bytes[copied..]));
+    expanded
+}
+
+/// A NULL-terminated array of C strings, like the `argv` and `envp`
+/// arrays passed to `execve`, which owns the strings it points to.  Use
+/// `Command::build_c_argv` and `Command::build_c_envp` to get one for your
+/// own calls into the C library, such as `posix_spawn`.
+///
+/// The pointers returned by `as_ptr` and `as_slice` borrow from the array,
+/// and are valid for as long as it's alive, even if it's moved.
+///
+/// This is only available on Unix.
+///
+/// ```
+/// use std::ffi::CStr;
+///
+/// let argv = exec::Command::new("echo").arg("hello").build_c_argv().unwrap();
+/// assert_eq!(argv.len(), 2);
+/// assert_eq!(argv.iter().nth(1).unwrap().to_bytes(), b"hello");
+/// assert!(argv.as_slice()[2].is_null());
+///
+/// let ptr = argv.as_ptr();
+/// let moved = Box::new(argv);
+/// assert_eq!(unsafe { CStr::from_ptr(*ptr.add(1)) }.to_bytes(), b"hello");
+/// # drop(moved);
+/// ```
+#[cfg(unix)]
+pub struct CStringArray {
+    /// The strings, each followed by its NUL, back to back in a single
+    /// allocation.
+    bytes: Vec<u8>,
+    /// Pointers to the start of each string in `bytes`, and the final NULL.
+    ptrs: PtrBuf,
+}
+
+/// How many pointers a `CStringArray` holds without allocating: enough for
+/// seven strings and the final NULL, which covers most command lines.
+#[cfg(unix)]
+const INLINE_PTRS: usize = 8;
+
+/// The pointers in a `CStringArray`, kept inline while there are few of
+/// them.  That's only safe for the arrays we build to exec with ourselves,
+/// which never move once we've taken a pointer to them; the ones we hand
+/// out are moved to the heap with `into_heap`.
+#[cfg(unix)]
+enum PtrBuf {
+    Inline([*const libc::c_char; INLINE_PTRS], usize),
+    Heap(Vec<*const libc::c_char>),
+}
+
+#[cfg(unix)]
+impl PtrBuf {
+    fn push(&mut self, ptr: *const libc::c_char) {
+        match self {
+            PtrBuf::Inline(ptrs, len) if *len < INLINE_PTRS => {
+                ptrs[*len] = ptr;
+                *len += 1;
+            }
+            PtrBuf::Inline(ptrs, _) => {
+                let mut heap = Vec::with_capacity(INLINE_PTRS * 2);
+                heap.extend_from_slice(ptrs);
+                heap.push(ptr);
+                *self = PtrBuf::Heap(heap);
+            }
+            PtrBuf::Heap(ptrs) => ptrs.push(ptr),
+        }
+    }
+
+    /// Move the pointers to the heap, so that they stay where they are
+    /// when the `PtrBuf` itself is moved.
+    fn into_heap(self) -> PtrBuf {
+        match self {
+            PtrBuf::Inline(ptrs, len) => PtrBuf::Heap(ptrs[..len].to_vec()),
+            heap => heap,
+        }
+    }
+
+    fn as_slice(&self) -> &[*const libc::c_char] {
+        match self {
+            PtrBuf::Inline(ptrs, len) => &ptrs[..*len],
+            PtrBuf::Heap(ptrs) => ptrs,
+        }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [*const libc::c_char] {
+        match self {
+            PtrBuf::Inline(ptrs, len) => &mut ptrs[..*len],
+            PtrBuf::Heap(ptrs) => ptrs,
+        }
+    }
+}
+
+#[cfg(unix)]
+impl CStringArray {
+    fn empty() -> CStringArray {
+        CStringArray {
+            bytes: vec![],
+            ptrs: PtrBuf::Inline([ptr::null(); INLINE_PTRS], 0),
+        }
+    }
+
+    /// Append the string made by joining `parts`.  Until `finish` is
+    /// called, the pointers just hold the offset of each string, since
+    /// `bytes` may still move as it grows.
+    fn push(&mut self, parts: &[&[u8]]) -> Result<(), Error> {
+        use std::os::unix::ffi::OsStrExt;
+
+        let start = self.bytes.len();
+        for part in parts {
+            self.bytes.extend_from_slice(part);
+        }
+        if self.bytes[start..].contains(&0) {
+            return Err(null_byte(OsStr::from_bytes(&self.bytes[start..])));
+        }
+        self.bytes.push(0);
+        self.ptrs
+            .push(ptr::null::<libc::c_char>().wrapping_add(start));
+        Ok(())
+    }
+
+    /// Add the final NULL, and point the pointers at the strings.
+    fn finish(mut self) -> CStringArray {
+        let base = self.bytes.as_ptr() as *const libc::c_char;
+        for ptr in self.ptrs.as_mut_slice() {
+            *ptr = base.wrapping_add(*ptr as usize);
+        }
+        self.ptrs.push(ptr::null());
+        self
+    }
+
+    /// Make sure the pointers don't live inside the array itself, so that
+    /// the caller can move it after calling `as_ptr`.
+    fn into_heap(self) -> CStringArray {
+        CStringArray {
+            bytes: self.bytes,
+            ptrs: self.ptrs.into_heap(),
+        }
+    }
+
+    /// Convert arguments, as used for `argv`.  Each argument is copied
+    /// once, straight into the shared buffer, and `Command` passes them
+    /// borrowed from the builder, so for a short command line the only
+    /// memory allocated here is that buffer, as it grows.
+    fn from_args<I>(args: I) -> Result<CStringArray, Error>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<OsStr>,
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut array = CStringArray::empty();
+        for (i, arg) in args.into_iter().enumerate() {
+            array
+                .push(&[arg.as_ref().as_bytes()])
+                .map_err(|err| err.at_index(i))?;
+        }
+        Ok(array.finish())
+    }
+
+    /// Convert environment variables to `KEY=VALUE` strings, as used for
+    /// `envp`.
+    fn from_env<E, K, V>(env: E) -> Result<CStringArray, Error>
+    where
+        E: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut array = CStringArray::empty();
+        for (key, val) in env {
+            let key = key.as_ref().as_bytes();
+            if key.contains(&b'=') {
+                return Err(Error::InvalidEnvKey);
+            }
+            array.push(&[key, b"=", val.as_ref().as_bytes()])?;
+        }
+        Ok(array.finish())
+    }
+
+    /// A pointer to the array, suitable for passing as `argv` or `envp`.
+    pub fn as_ptr(&self) -> *const *const libc::c_char {
+        self.as_slice().as_ptr()
+    }
+
+    /// The array of pointers, including the final NULL.
+    pub fn as_slice(&self) -> &[*const libc::c_char] {
+        self.ptrs.as_slice()
+    }
+
+    /// The number of strings, not counting the final NULL.
+    pub fn len(&self) -> usize {
+        self.as_slice().len() - 1
+    }
+
+    /// Are there no strings at all?
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the strings.
+    pub fn iter(&self) -> impl Iterator<Item = &std::ffi::CStr> {
+        let ptrs = self.as_slice();
+        ptrs[..ptrs.len() - 1]
+            .iter()
+            .map(|&ptr| unsafe { std::ffi::CStr::from_ptr(ptr) })
+    }
+}
+
+#[cfg(unix)]
+impl fmt::Debug for CStringArray {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Where to connect one of the program's standard streams.  Used with
+/// `Command::stdin`, `Command::stdout` and `Command::stderr`.
+///
+/// This is only available on Unix.
+#[cfg(unix)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Stdio {
+    /// Leave the stream connected to whatever the current process has.
+    Inherit,
+    /// Connect the stream to a file descriptor we already have open.  The
+    /// descriptor is closed once it's been duplicated onto the stream.
+    Fd(RawFd),
+    /// Open a file and connect the stream to it.  Standard input is opened
+    /// for reading, and standard output and error are opened for writing,
+    /// creating the file or truncating it as needed.
+    Path(PathBuf),
+    /// Open a file using the given `open` flags, such as
+    /// `libc::O_WRONLY | libc::O_APPEND | libc::O_CREAT`, and connect the
+    /// stream to it.  If the file is created, it gets mode `0o666`, less
+    /// the umask.
+    PathWithFlags(PathBuf, libc::c_int),
+}
+
+#[cfg(unix)]
+impl Stdio {
+    /// Open or duplicate the descriptor to connect to stream `target`.
+    /// Returns the descriptor, and whether we should close it afterwards.
+    ///
+    /// Files are opened close-on-exec, so that they can't leak into the
+    /// program however we fail to clean up.  `dup2` clears the flag on
+    /// the copy, and we clear it ourselves if the file happened to be
+    /// opened as `target`.
+    fn open(&self, target: RawFd) -> Result<Option<(RawFd, bool)>, Error> {
+        let (path, flags) = match self {
+            Stdio::Inherit => return Ok(None),
+            Stdio::Fd(fd) => return Ok(Some((*fd, *fd > 2))),
+            Stdio::Path(path) if target == 0 => (path, libc::O_RDONLY),
+            Stdio::Path(path) => (path, libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC),
+            Stdio::PathWithFlags(path, flags) => (path, *flags),
+        };
+        let path = to_cstring(path.as_os_str())?;
+        let flags = flags | libc::O_CLOEXEC;
+        let fd = cvt_r(|| unsafe { libc::open(path.as_ptr(), flags, 0o666 as libc::c_uint) })?;
+        if fd == target {
+            set_cloexec(fd, false)?;
+        }
+        // If a closed standard stream was reused, it's either connected to
+        // something later or closed again by `exec`.
+        Ok(Some((fd, fd > 2)))
+    }
+}
+
+/// Turn the result of one of the `posix_spawn` functions, which return
+/// an error code instead of setting `errno`, into a `Result`.
+#[cfg(unix)]
+fn check_spawn(res: libc::c_int) -> Result<(), Error> {
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(Error::Errno(Errno(res)))
+    }
+}
+
+/// The `posix_spawn` file actions that connect the standard streams, and
+/// the files we opened for them, which are closed when it's dropped.
+#[cfg(unix)]
+struct SpawnFileActions(libc::posix_spawn_file_actions_t, Vec<RawFd>);
+
+#[cfg(unix)]
+impl SpawnFileActions {
+    /// Files are opened here, rather than by the child with `addopen`, so
+    /// that a relative path is found from the root and current directory
+    /// we had before `setup_for` changed them, as it is with `exec`.
+    fn new(stdio: &[Stdio; 3]) -> Result<SpawnFileActions, Error> {
+        let mut actions = SpawnFileActions(unsafe { std::mem::zeroed() }, vec![]);
+        check_spawn(unsafe { libc::posix_spawn_file_actions_init(&mut actions.0) })?;
+        let mut to_close = vec![];
+        for (target, stdio) in stdio.iter().enumerate() {
+            let target = target as RawFd;
+            let res = match stdio.open(target)? {
+                None => 0,
+                Some((fd, close)) => {
+                    match stdio {
+                        Stdio::Fd(_) => {
+                            if close && !to_close.contains(&fd) {
+                                to_close.push(fd);
+                            }
+                        }
+                        _ => actions.1.push(fd),
+                    }
+                    if fd == target {
+                        0
+                    } else {
+                        unsafe {
+                            libc::posix_spawn_file_actions_adddup2(&mut actions.0, fd, target)
+                        }
+                    }
+                }
+            };
+            check_spawn(res)?;
+        }
+        for fd in to_close {
+            check_spawn(unsafe { libc::posix_spawn_file_actions_addclose(&mut actions.0, fd) })?;
+        }
+        Ok(actions)
+    }
+}
+
+#[cfg(unix)]
+impl Drop for SpawnFileActions {
+    fn drop(&mut self) {
+        unsafe { libc::posix_spawn_file_actions_destroy(&mut self.0) };
+        for &fd in &self.1 {
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
+/// The `posix_spawn` attributes for the session, process group and signal
+/// settings of a `Command`.
+#[cfg(unix)]
+struct SpawnAttr(libc::posix_spawnattr_t);
+
+#[cfg(unix)]
+impl SpawnAttr {
+    fn new(cmd: &Command) -> Result<SpawnAttr, Error> {
+        let mut attr = SpawnAttr(unsafe { std::mem::zeroed() });
+        check_spawn(unsafe { libc::posix_spawnattr_init(&mut attr.0) })?;
+        if cmd.controlling_tty.is_some() {
+            return Err(Error::Errno(Errno(libc::ENOTSUP)));
+        }
+        let mut flags: libc::c_int = 0;
+        if cmd.setsid {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            {
+                flags |= libc::c_int::from(libc::POSIX_SPAWN_SETSID);
+            }
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            {
+                return Err(Error::Errno(Errno(libc::ENOTSUP)));
+            }
+        }
+        if let Some(pgid) = cmd.process_group {
+            flags |= libc::c_int::from(libc::POSIX_SPAWN_SETPGROUP);
+            check_spawn(unsafe { libc::posix_spawnattr_setpgroup(&mut attr.0, pgid) })?;
+        }
+        if cmd.reset_signals || cmd.restore_sigpipe || !cmd.default_signals.is_empty() {
+            let mut set: libc::sigset_t = unsafe { std::mem::zeroed() };
+            if cmd.reset_signals {
+                unsafe { libc::sigfillset(&mut set) };
+            } else {
+                unsafe { libc::sigemptyset(&mut set) };
+                if cmd.restore_sigpipe {
+                    unsafe { libc::sigaddset(&mut set, libc::SIGPIPE) };
+                }
+                for &sig in &cmd.default_signals {
+                    cvt(unsafe { libc::sigaddset(&mut set, sig) })?;
+                }
+            }
+            flags |= libc::c_int::from(libc::POSIX_SPAWN_SETSIGDEF);
+            check_spawn(unsafe { libc::posix_spawnattr_setsigdefault(&mut attr.0, &set) })?;
+        }
+        if cmd.reset_signals {
+            let mut set: libc::sigset_t = unsafe { std::mem::zeroed() };
+            unsafe { libc::sigemptyset(&mut set) };
+            flags |= libc::c_int::from(libc::POSIX_SPAWN_SETSIGMASK);
+            check_spawn(unsafe { libc::posix_spawnattr_setsigmask(&mut attr.0, &set) })?;
+        }
+        check_spawn(unsafe {
+            libc::posix_spawnattr_setflags(&mut attr.0, flags as libc::c_short)
+        })?;
+        Ok(attr)
+    }
+}
+
+#[cfg(unix)]
+impl Drop for SpawnAttr {
+    fn drop(&mut self) {
+        unsafe { libc::posix_spawnattr_destroy(&mut self.0) };
+    }
+}
+
+/// A closure registered with `Command::pre_exec`.  These are shared
+/// between clones of a `Command`, since closures can't be cloned.
+type PreExecFn = Arc<Mutex<dyn FnMut() -> io::Result<()> + Send + Sync>>;
+
+/// A predicate registered with `Command::env_retain`.  These are shared
+/// between clones of a `Command` like `PreExecFn`.
+type EnvRetainFn = Arc<Mutex<dyn FnMut(&OsStr, &OsStr) -> bool + Send + Sync>>;
+
+/// The closure registered with `Command::on_exec`.  It's shared between
+/// clones of a `Command` like `PreExecFn`, and can only be called once.
+type OnExecFn = Arc<Mutex<Option<Box<dyn FnOnce(&OsStr, &[OsString]) + Send>>>>;
+
+/// Build a command to execute.  This has an API which is deliberately
+/// similar to `std::process::Command`.
+///
+/// ```no_run
+/// let err = exec::Command::new("echo")
+///     .arg("hello")
+///     .arg("world")
+///     .exec();
+/// println!("Error: {}", err);
+/// ```
+///
+/// If the `exec` function succeeds, it will never return.
+///
+/// A `Command` can be cloned to build several variations on a common
+/// base.  Clones get their own copies of the arguments and other
+/// settings, but closures registered with `pre_exec` are shared by all
+/// of them, so any state captured by such a closure is shared too.
+///
+/// ```
+/// let mut git = exec::Command::new("git");
+/// git.arg("--no-pager");
+/// let mut log = git.clone();
+/// log.arg("log");
+/// assert_eq!(git.get_args().count(), 1);
+/// assert_eq!(log.get_args().count(), 2);
+/// ```
+///
+/// A `Command` does nothing until it's run, so the `unused_must_use` lint
+/// reports a `Command` value that's dropped without being used, like one
+/// that's created and never touched again:
+///
+/// ```compile_fail
+/// #![deny(unused_must_use)]
+/// exec::Command::new("ls");
+/// ```
+///
+/// That doesn't cover a chain like `Command::new("ls").arg("-l");`, whose
+/// value is the `&mut Command` returned by `arg`, so a forgotten `exec` at
+/// the end of a chain isn't reported.  The builder methods that return
+/// `&mut Command` are deliberately not marked `#[must_use]`, since calling
+/// them as statements, as above, is the usual way to configure a command
+/// step by step.
+#[derive(Clone)]
+#[must_use = "a `Command` does nothing unless it is run with `exec` or a similar method"]
+pub struct Command {
+    /// The program name and arguments, in typical C `argv` style.
+    argv: Vec<OsString>,
+    /// A value to pass as `argv[0]` instead of the program name.
+    arg0: Option<OsString>,
+    /// The indices in `argv` of arguments added with `raw_arg`.
+    #[cfg(windows)]
+    raw_args: Vec<usize>,
+    /// The exact path of the program to run, without searching `PATH`.
+    program_path: Option<OsString>,
+    /// The directories to search for the program instead of our `PATH`.
+    search_path: Option<OsString>,
+    /// Should we refuse to run a program not given by an absolute path?
+    require_absolute_path: bool,
+    /// The directory to change to before running the program.
+    current_dir: Option<PathBuf>,
+    /// The directory to make the root directory before running the
+    /// program.
+    #[cfg(unix)]
+    chroot: Option<PathBuf>,
+    /// The user ID to switch to before running the program.
+    #[cfg(unix)]
+    uid: Option<u32>,
+    /// The group ID to switch to before running the program.
+    #[cfg(unix)]
+    gid: Option<u32>,
+    /// The supplementary group IDs to switch to before running the program.
+    #[cfg(unix)]
+    groups: Option<Vec<u32>>,
+    /// Where to connect standard input, output and error.
+    #[cfg(unix)]
+    stdio: [Stdio; 3],
+    /// File descriptors numbered this or higher are closed before running
+    /// the program.
+    #[cfg(unix)]
+    close_fds_from: Option<RawFd>,
+    /// The file descriptors to keep open when every other one is marked
+    /// close-on-exec, if we should do that.  This always includes standard
+    /// input, output and error.
+    #[cfg(unix)]
+    keep_fds: Option<Vec<RawFd>>,
+    /// The descriptor to write a record of `argv` to just before running
+    /// the program.
+    #[cfg(unix)]
+    audit_fd: Option<RawFd>,
+    /// Should the program be started in a new session?
+    #[cfg(unix)]
+    setsid: bool,
+    /// Should we give up our controlling terminal before running the
+    /// program?
+    #[cfg(unix)]
+    detach: bool,
+    /// The terminal to make our controlling terminal with `TIOCSCTTY`
+    /// after starting a new session.
+    #[cfg(unix)]
+    controlling_tty: Option<RawFd>,
+    /// The process group to move to before running the program.
+    #[cfg(unix)]
+    process_group: Option<i32>,
+    /// The file mode creation mask to set before running the program.
+    #[cfg(unix)]
+    umask: Option<u32>,
+    /// Resource limits to set before running the program, as `(resource,
+    /// soft, hard)`.
+    #[cfg(unix)]
+    rlimits: Vec<(i32, u64, u64)>,
+    /// The amount to add to the scheduling priority before running the
+    /// program.
+    #[cfg(unix)]
+    nice: Option<i32>,
+    /// Should signal dispositions and the signal mask be reset to their
+    /// defaults before running the program?
+    #[cfg(unix)]
+    reset_signals: bool,
+    /// Should `SIGPIPE` be reset to its default disposition before running
+    /// the program?
+    #[cfg(unix)]
+    restore_sigpipe: bool,
+    /// Signals to reset to their default dispositions before running the
+    /// program.
+    #[cfg(unix)]
+    default_signals: Vec<i32>,
+    /// Should a program that fails with `ENOEXEC` be run as a shell script?
+    #[cfg(unix)]
+    shell_fallback: bool,
+    /// Closures to call right before running the program.
+    pre_exec: Vec<PreExecFn>,
+    /// A closure to call with the program and its arguments just before
+    /// it's run.
+    on_exec: Option<OnExecFn>,
+    /// Environment variables to set in the new program, on top of the
+    /// ones it inherits from us.  A value of `None` means the variable
+    /// should be removed.
+    env_vars: BTreeMap<OsString, Option<OsString>>,
+    /// The variables in `env_vars` that were set to a different value or
+    /// removed after being set, or the other way around.
+    env_conflicts: Vec<OsString>,
+    /// Should `env_conflicts` be treated as an error?
+    strict_env: bool,
+    /// Should the new program start with an empty environment instead of
+    /// inheriting ours?
+    env_clear: bool,
+    /// Variables to copy from our environment even though `env_clear` is
+    /// set.
+    env_inherit: Vec<OsString>,
+    /// The environment to pass instead of our live one, set by
+    /// `env_capture` or `env_from`.
+    env_base: Option<Vec<(OsString, OsString)>>,
+    /// Predicates that decide which variables are kept from the
+    /// environment we start from, before `env_vars` is applied.
+    env_retain: Vec<EnvRetainFn>,
+    /// Should `$NAME` references in the arguments be replaced with the
+    /// values of environment variables?
+    expand_env: bool,
+    /// The OS error codes for which `exec_or_spawn` should spawn the
+    /// program instead.
+    spawn_fallback: Vec<i32>,
+    /// Has this command been run or checked?  Only tracked for the warning
+    /// from the `warn-unexecuted` feature.
+    #[cfg(all(feature = "warn-unexecuted", debug_assertions))]
+    used: UsedFlag,
+}
+
+/// Whether a `Command` has been used, for the `warn-unexecuted` feature.
+/// Clones start out unused, since each of them is meant to be run.
+#[cfg(all(feature = "warn-unexecuted", debug_assertions))]
+#[derive(Default)]
+struct UsedFlag(std::sync::atomic::AtomicBool);
+
+#[cfg(all(feature = "warn-unexecuted", debug_assertions))]
+impl Clone for UsedFlag {
+    fn clone(&self) -> UsedFlag {
+        UsedFlag::default()
+    }
+}
+
+/// With the `warn-unexecuted` feature, in debug builds, warn on standard
+/// error about a `Command` that's dropped without ever being run or
+/// checked, which usually means a forgotten call to `exec`.
+#[cfg(all(feature = "warn-unexecuted", debug_assertions))]
+impl Drop for Command {
+    fn drop(&mut self) {
+        use std::sync::atomic::Ordering;
+
+        if !self.used.0.load(Ordering::Relaxed) && !std::thread::panicking() {
+            eprintln!(
+                "warning: exec::Command for {:?} dropped without being run",
+                self.argv[0]
+            );
+        }
+    }
+}
+
+impl Command {
+    /// Create a new command builder, specifying the program to run.  The
+    /// program will be searched for using the usual rules for `PATH`.
+    pub fn new<S: AsRef<OsStr>>(program: S) -> Command {
+        Command {
+            argv: vec![program.as_ref().to_owned()],
+            arg0: None,
+            #[cfg(windows)]
+            raw_args: vec![],
+            program_path: None,
+            search_path: None,
+            require_absolute_path: false,
+            current_dir: None,
+            #[cfg(unix)]
+            chroot: None,
+            #[cfg(unix)]
+            uid: None,
+            #[cfg(unix)]
+            gid: None,
+            #[cfg(unix)]
+            groups: None,
+            #[cfg(unix)]
+            stdio: [Stdio::Inherit, Stdio::Inherit, Stdio::Inherit],
+            #[cfg(unix)]
+            close_fds_from: None,
+            #[cfg(unix)]
+            keep_fds: None,
+            #[cfg(unix)]
+            audit_fd: None,
+            #[cfg(unix)]
+            setsid: false,
+            #[cfg(unix)]
+            detach: false,
+            #[cfg(unix)]
+            controlling_tty: None,
+            #[cfg(unix)]
+            process_group: None,
+            #[cfg(unix)]
+            umask: None,
+            #[cfg(unix)]
+            rlimits: vec![],
+            #[cfg(unix)]
+            nice: None,
+            #[cfg(unix)]
+            reset_signals: false,
+            #[cfg(unix)]
+            restore_sigpipe: false,
+            #[cfg(unix)]
+            default_signals: vec![],
+            #[cfg(unix)]
+            shell_fallback: false,
+            pre_exec: vec![],
+            on_exec: None,
+            env_vars: BTreeMap::new(),
+            env_conflicts: vec![],
+            strict_env: false,
+            env_clear: false,
+            env_inherit: vec![],
+            env_base: None,
+            env_retain: vec![],
+            expand_env: false,
+            spawn_fallback: vec![],
+            #[cfg(all(feature = "warn-unexecuted", debug_assertions))]
+            used: UsedFlag::default(),
+        }
+    }
+
+    /// Create a new command builder from a complete `argv`, using the first
+    /// element as the program and the rest as its arguments.  Returns
+    /// `None` if `argv` is empty.
+    ///
+    /// ```
+    /// let cmd = exec::Command::from_argv(vec!["echo", "hello"]).unwrap();
+    /// assert_eq!(cmd.get_program(), "echo");
+    /// assert!(exec::Command::from_argv(Vec::<String>::new()).is_none());
+    /// ```
+    pub fn from_argv<I, S>(argv: I) -> Option<Command>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut argv = argv.into_iter();
+        let mut cmd = Command::new(argv.next()?);
+        cmd.args(argv);
+        Some(cmd)
+    }
+
+    /// Create a new command builder for `program` with the arguments
+    /// `args`, which become `argv[1]` onwards.  This is the same as `new`
+    /// followed by `args`, in a single expression.
+    ///
+    /// ```
+    /// let cmd = exec::Command::with_args("echo", &["hello", "world"]);
+    /// assert_eq!(cmd.get_program(), "echo");
+    /// assert_eq!(cmd.get_args().collect::<Vec<_>>(), ["hello", "world"]);
+    /// ```
+    pub fn with_args<S, I, A>(program: S, args: I) -> Command
+    where
+        S: AsRef<OsStr>,
+        I: IntoIterator<Item = A>,
+        A: AsRef<OsStr>,
+    {
+        let mut cmd = Command::new(program);
+        cmd.args(args);
+        cmd
+    }
+
+    /// Pass `arg` to the new program as `argv[0]`, instead of the name of
+    /// the program being run.  The program itself is still located using
+    /// the name passed to `new`.  This can be chained.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("/bin/busybox")
+    ///     .arg0("ls")
+    ///     .arg("-l")
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    ///
+    /// The name passed to `new` is replaced, not joined by `arg`, so the
+    /// arguments follow it directly:
+    ///
+    /// ```
+    /// let mut cmd = exec::Command::new("/bin/ls");
+    /// cmd.arg0("ls").arg("-l");
+    /// let argv = cmd.build_c_argv().unwrap();
+    /// let argv: Vec<_> = argv.iter().map(|arg| arg.to_str().unwrap()).collect();
+    /// assert_eq!(argv, ["ls", "-l"]);
+    /// assert_eq!(cmd.get_program(), "/bin/ls");
+    /// assert_eq!(cmd.get_resolved_program().unwrap(), std::path::Path::new("/bin/ls"));
+    /// ```
+    pub fn arg0<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Command {
+        self.arg0 = Some(arg.as_ref().to_owned());
+        self
+    }
+
+    /// Run the program at `path`, without searching `PATH` for it.  The
+    /// name passed to `new` is then only used as `argv[0]`, unless that's
+    /// also overridden with `arg0`.  This can be chained.
+    ///
+    /// This keeps the three things `exec` needs separate: the file that's
+    /// loaded comes from `program_path` if it's set, and otherwise from
+    /// searching `PATH` for the name passed to `new`; `argv[0]` comes from
+    /// `arg0` if it's set, and otherwise is the name passed to `new`.
+    ///
+    /// ```
+    /// // This only succeeds if the program is really loaded from `/bin/sh`.
+    /// let err = exec::Command::new("no-such-program")
+    ///     .program_path("/bin/sh")
+    ///     .args(&["-c", "exit 0"])
+    ///     .exec();
+    /// panic!("Error: {}", err);
+    /// ```
+    pub fn program_path<P: AsRef<OsStr>>(&mut self, path: P) -> &mut Command {
+        self.program_path = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// Search the directories in `path`, which is in the same format as
+    /// the `PATH` environment variable, for the program, instead of our own
+    /// `PATH`.  The program is then run by its full path, so this doesn't
+    /// change the `PATH` the program sees, and `env("PATH", ...)` doesn't
+    /// change where it's found.  If it isn't found, `exec` fails with
+    /// `ENOENT`.  This is ignored if `program_path` is set, and can be
+    /// chained.
+    ///
+    /// Like the search `execvp` does, this happens after `chroot` and
+    /// `current_dir` have been applied, so the directories are looked up
+    /// inside the new root, and relative ones are relative to the new
+    /// current directory.
+    ///
+    /// Under Miri, which isn't allowed to look at the filesystem, the search
+    /// is skipped, so `exec` returns `Error::DryRun` whether or not the
+    /// program would have been found.
+    ///
+    /// ```
+    /// let err = exec::Command::new("sh")
+    ///     .with_path("/nonexistent:/bin")
+    ///     .env("PATH", "/nonexistent")
+    ///     .args(&["-c", "test \"$PATH\" = /nonexistent"])
+    ///     .exec();
+    /// panic!("Error: {}", err);
+    /// ```
+    ///
+    /// A relative directory is found from `current_dir`:
+    ///
+    /// ```
+    /// # #[cfg(unix)]
+    /// # {
+    /// let dir = std::env::temp_dir().join("exec-with-path-doctest");
+    /// std::fs::create_dir_all(dir.join("bin")).unwrap();
+    /// let _ = std::os::unix::fs::symlink("/bin/sh", dir.join("bin/found-here"));
+    /// let err = exec::Command::new("found-here")
+    ///     .with_path("bin")
+    ///     .current_dir(&dir)
+    ///     .args(&["-c", "exit 0"])
+    ///     .exec();
+    /// panic!("Error: {}", err);
+    /// # }
+    /// ```
+    pub fn with_path<S: AsRef<OsStr>>(&mut self, path: S) -> &mut Command {
+        self.search_path = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// Make `exec` and `validate` fail with `Error::RelativePath` unless the
+    /// program, or the path given to `program_path`, is an absolute path,
+    /// so that it's never looked up in `PATH` or the current directory.
+    /// This can be chained.
+    ///
+    /// This is meant for code running with more privileges than whoever
+    /// controls its environment, where a `PATH` lookup could be hijacked.
+    /// The program's own name is checked, so a bare name fails even if
+    /// `with_path` would find it somewhere.
+    ///
+    /// ```
+    /// use std::ffi::OsString;
+    ///
+    /// let mut cmd = exec::Command::new("sh");
+    /// cmd.require_absolute_path();
+    /// assert_eq!(cmd.validate(), Err(exec::Error::RelativePath(OsString::from("sh"))));
+    /// cmd.program_path("/bin/sh");
+    /// assert_eq!(cmd.validate(), Ok(()));
+    /// ```
+    pub fn require_absolute_path(&mut self) -> &mut Command {
+        self.require_absolute_path = true;
+        self
+    }
+
+    /// Add an argument to the command builder.  This can be chained.
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Command {
+        self.argv.push(arg.as_ref().to_owned());
+        self
+    }
+
+    /// Add an argument we already own to the command builder, without
+    /// copying it the way `arg` does.  This can be chained.
+    ///
+    /// ```
+    /// use std::ffi::OsString;
+    ///
+    /// let mut cmd = exec::Command::new("echo");
+    /// cmd.arg_owned(OsString::from(format!("{}-{}", "hello", "world")));
+    /// assert_eq!(cmd.get_args().collect::<Vec<_>>(), &["hello-world"]);
+    /// ```
+    pub fn arg_owned(&mut self, arg: OsString) -> &mut Command {
+        self.argv.push(arg);
+        self
+    }
+
+    /// Add an argument to the command line exactly as it is, without the
+    /// quoting that `arg` applies.  This can be chained.
+    ///
+    /// This is only available on Windows, where programs parse their own
+    /// command line and some, like `cmd.exe`, don't follow the usual
+    /// rules.  It mirrors `std::os::windows::process::CommandExt::raw_arg`.
+    /// Raw and normal arguments can be mixed freely, but it's up to you to
+    /// make sure the raw parts mean what you want to the program.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("cmd.exe")
+    ///     .arg("/c")
+    ///     .raw_arg(r#""echo "hello world"""#)
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    #[cfg(windows)]
+    pub fn raw_arg<S: AsRef<OsStr>>(&mut self, raw: S) -> &mut Command {
+        self.raw_args.push(self.argv.len());
+        self.arg(raw)
+    }
+
+    /// Add multiple arguments to the command builder.  This accepts
+    /// anything that can be iterated over, including slices, vectors, and
+    /// iterators like `std::env::args_os()`.  This can be chained.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("echo")
+    ///     .args(&["hello", "world"])
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    ///
+    /// ```no_run
+    /// // Pass along all of our own arguments.
+    /// let err = exec::Command::new("echo")
+    ///     .args(std::env::args_os().skip(1))
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    pub fn args<I, S>(&mut self, args: I) -> &mut Command
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        for arg in args {
+            self.arg(arg.as_ref());
+        }
+        self
+    }
+
+    /// Add all of our own arguments, as returned by `std::env::args_os`,
+    /// except for our own `argv[0]`, for a wrapper that passes everything
+    /// on to another program.  This can be chained.
+    ///
+    /// ```
+    /// // Re-exec ourselves with known arguments, and have the fresh copy
+    /// // forward them to a script that checks what it was given.
+    /// let err = if std::env::var_os("EXEC_DOCTEST_FORWARD").is_none() {
+    ///     exec::current_exe_command()
+    ///         .unwrap()
+    ///         .args(["a", "b c"])
+    ///         .env("EXEC_DOCTEST_FORWARD", "1")
+    ///         .exec()
+    /// } else {
+    ///     exec::Command::new("sh")
+    ///         .args(["-c", r#"test "$0" = sh && test $# -eq 2 && test "$1" = a && test "$2" = "b c""#, "sh"])
+    ///         .forward_args()
+    ///         .exec()
+    /// };
+    /// panic!("Error: {}", err);
+    /// ```
+    pub fn forward_args(&mut self) -> &mut Command {
+        self.args(env::args_os().skip(1))
+    }
+
+    /// Add `arg` to the command builder only if `cond` is true, without
+    /// breaking up a chain of calls.  This can be chained.
+    ///
+    /// ```
+    /// let verbose = false;
+    /// let mut cmd = exec::Command::new("ls");
+    /// cmd.arg_if(true, "-l").arg_if(verbose, "-v");
+    /// let args: Vec<_> = cmd.get_args().collect();
+    /// assert_eq!(args, &["-l"]);
+    /// ```
+    pub fn arg_if<S: AsRef<OsStr>>(&mut self, cond: bool, arg: S) -> &mut Command {
+        if cond {
+            self.arg(arg);
+        }
+        self
+    }
+
+    /// Add multiple arguments to the command builder only if `cond` is
+    /// true.  This accepts the same arguments as `args`, and can be
+    /// chained.
+    ///
+    /// ```
+    /// let mut cmd = exec::Command::new("git");
+    /// cmd.arg("log").args_if(false, &["--author", "me"]);
+    /// assert_eq!(cmd.get_args().count(), 1);
+    /// cmd.args_if(true, &["-n", "1"]);
+    /// let args: Vec<_> = cmd.get_args().collect();
+    /// assert_eq!(args, &["log", "-n", "1"]);
+    /// ```
+    pub fn args_if<I, S>(&mut self, cond: bool, args: I) -> &mut Command
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        if cond {
+            self.args(args);
+        }
+        self
+    }
+
+    /// Remove all the arguments added so far.  The program, and any
+    /// `argv[0]` set with `arg0`, are kept.  This can be chained.
+    ///
+    /// ```
+    /// let mut cmd = exec::Command::new("echo");
+    /// cmd.arg0("greet").args(&["hello", "world"]).clear_args();
+    /// assert_eq!(cmd.get_args().count(), 0);
+    /// assert_eq!(cmd.get_program(), "echo");
+    /// ```
+    pub fn clear_args(&mut self) -> &mut Command {
+        self.argv.truncate(1);
+        #[cfg(windows)]
+        self.raw_args.clear();
+        self
+    }
+
+    /// Replace all the arguments added so far with `args`, as if
+    /// `clear_args` were called before `args`.  This is handy for reusing
+    /// a clone of a command as a template.  This can be chained.
+    ///
+    /// ```
+    /// let mut base = exec::Command::new("git");
+    /// base.arg("status").env("GIT_PAGER", "cat");
+    /// let mut log = base.clone();
+    /// log.set_args(&["log", "--oneline"]);
+    /// assert_eq!(log.get_args().collect::<Vec<_>>(), &["log", "--oneline"]);
+    /// ```
+    pub fn set_args<I, S>(&mut self, args: I) -> &mut Command
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.clear_args().args(args)
+    }
+
+    /// Set an environment variable in the new program.  All other
+    /// variables are inherited from the current process as usual.  This
+    /// can be chained.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("sh")
+    ///     .args(&["-c", "echo $GREETING"])
+    ///     .env("GREETING", "hello")
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    ///
+    /// Changing the environment doesn't stop a bare program name from
+    /// being found by searching `PATH`.  Our own `PATH` is searched, not the
+    /// one the program will get, using `execvpe` where the C library has
+    /// it, and otherwise by trying each directory with `execve` ourselves:
+    ///
+    /// ```
+    /// let err = exec::Command::new("sh")
+    ///     .args(&["-c", "test \"$PATH\" = /nonexistent"])
+    ///     .env("PATH", "/nonexistent")
+    ///     .exec();
+    /// panic!("Error: {}", err);
+    /// ```
+    pub fn env<K, V>(&mut self, key: K, val: V) -> &mut Command
+    where
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.set_env_var(key.as_ref().to_owned(), Some(val.as_ref().to_owned()));
+        self
+    }
+
+    /// Set several environment variables in the new program, as if `env`
+    /// were called for each pair in turn.  Later pairs override earlier
+    /// ones with the same name.  This can be chained.
+    ///
+    /// ```no_run
+    /// use std::collections::HashMap;
+    ///
+    /// let mut vars = HashMap::new();
+    /// vars.insert("PATH", "/bin:/usr/bin");
+    /// vars.insert("LANG", "C");
+    /// let err = exec::Command::new("/usr/bin/env")
+    ///     .env_clear()
+    ///     .envs(vars)
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    pub fn envs<I, K, V>(&mut self, vars: I) -> &mut Command
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        for (key, val) in vars {
+            self.env(key, val);
+        }
+        self
+    }
+
+    /// Like `env`, but checks the variable right away instead of when
+    /// `exec` is called, and returns an error instead of setting it if the
+    /// name contains `=` or either part contains a NUL byte.  This can be
+    /// chained with `?`.
+    ///
+    /// ```
+    /// let mut cmd = exec::Command::new("env");
+    /// assert!(cmd.try_env("GREETING", "hello").is_ok());
+    /// assert_eq!(
+    ///     cmd.try_env("FOO=BAR", "x").unwrap_err(),
+    ///     exec::Error::InvalidEnvKey
+    /// );
+    /// assert_eq!(
+    ///     cmd.try_env("FOO", "nul\0byte").unwrap_err(),
+    ///     exec::Error::NullByteInArgument {
+    ///         index: None,
+    ///         value: "FOO=nul\0byte".into(),
+    ///     }
+    /// );
+    /// assert_eq!(cmd.get_envs().count(), 1);
+    /// ```
+    pub fn try_env<K, V>(&mut self, key: K, val: V) -> Result<&mut Command, Error>
+    where
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        #[cfg(unix)]
+        env_cstring(key.as_ref(), val.as_ref())?;
+        #[cfg(windows)]
+        env_wide(key.as_ref(), val.as_ref())?;
+        Ok(self.env(key, val))
+    }
+
+    /// Like `envs`, but checks each variable as `try_env` does.  If one of
+    /// them is invalid, the error is returned and none of the variables
+    /// after it are set.  This can be chained with `?`.
+    ///
+    /// ```
+    /// let mut cmd = exec::Command::new("env");
+    /// let res = cmd.try_envs(vec![("LANG", "C"), ("BAD=KEY", "x"), ("TZ", "UTC")]);
+    /// assert_eq!(res.unwrap_err(), exec::Error::InvalidEnvKey);
+    /// assert_eq!(cmd.get_envs().count(), 1);
+    /// ```
+    pub fn try_envs<I, K, V>(&mut self, vars: I) -> Result<&mut Command, Error>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        for (key, val) in vars {
+            self.try_env(key, val)?;
+        }
+        Ok(self)
+    }
+
+    /// Remove an environment variable from the new program's environment,
+    /// even if the current process has it set.  This undoes any earlier
+    /// call to `env` for the same variable, and a later call to `env` will
+    /// set it again.  This can be chained.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("ls")
+    ///     .env_remove("LD_PRELOAD")
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    pub fn env_remove<K: AsRef<OsStr>>(&mut self, key: K) -> &mut Command {
+        self.set_env_var(key.as_ref().to_owned(), None);
+        self
+    }
+
+    /// Remove the environment variables that influence the dynamic linker,
+    /// like `LD_PRELOAD` and `LD_LIBRARY_PATH`, or `DYLD_INSERT_LIBRARIES`
+    /// and the other `DYLD_*` variables on macOS, as `env_remove` would.
+    /// This can be chained.
+    ///
+    /// This is only available on Unix, and is meant for wrappers running
+    /// with more privileges than the user who started them, where those
+    /// variables could be used to load arbitrary code into the program.
+    /// The list covers the variables known to the glibc, musl and BSD
+    /// linkers, or to `dyld` on macOS.  A later `env` call can still set
+    /// one of them deliberately.
+    ///
+    /// ```
+    /// let err = exec::Command::new("sh")
+    ///     .args(&["-c", "test -z \"${LD_PRELOAD-}${LD_LIBRARY_PATH-}\""])
+    ///     .env("LD_LIBRARY_PATH", "/tmp")
+    ///     .scrub_linker_env()
+    ///     .exec();
+    /// panic!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn scrub_linker_env(&mut self) -> &mut Command {
+        for key in LINKER_ENV {
+            self.env_remove(key);
+        }
+        self
+    }
+
+    /// If `enabled`, make `exec` and `validate` fail with
+    /// `Error::EnvConflict` if any environment variable was set to two
+    /// different values, or both set and removed, with `env`, `envs` and
+    /// `env_remove`, instead of silently keeping whichever came last.  This
+    /// is off by default, and can be chained.
+    ///
+    /// This catches mistakes in tools that put an environment together from
+    /// several sources.  Setting a variable to the same value twice, or
+    /// removing it twice, isn't a conflict, and `env_clear` forgets about
+    /// earlier conflicts along with the variables themselves.
+    ///
+    /// ```
+    /// use std::ffi::OsString;
+    ///
+    /// let mut cmd = exec::Command::new("env");
+    /// cmd.strict_env(true).env("LANG", "C").env("LANG", "C");
+    /// assert_eq!(cmd.validate(), Ok(()));
+    /// cmd.env_remove("LANG");
+    /// assert_eq!(cmd.validate(), Err(exec::Error::EnvConflict(OsString::from("LANG"))));
+    /// ```
+    pub fn strict_env(&mut self, enabled: bool) -> &mut Command {
+        self.strict_env = enabled;
+        self
+    }
+
+    /// Start the new program with an empty environment, discarding both
+    /// the variables inherited from the current process and any set by
+    /// earlier calls to `env`.  Variables set by later calls to `env` will
+    /// still be passed.  This can be chained.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("/usr/bin/env")
+    ///     .env_clear()
+    ///     .env("PATH", "/bin:/usr/bin")
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    pub fn env_clear(&mut self) -> &mut Command {
+        self.env_vars.clear();
+        self.env_conflicts.clear();
+        self.env_inherit.clear();
+        self.env_base = None;
+        self.env_retain.clear();
+        self.env_clear = true;
+        self
+    }
+
+    /// Start the new program with an empty environment, as with
+    /// `env_clear`, except for the variables named in `keys`, which are
+    /// copied from the current process when `exec` is called.  Any of
+    /// `keys` that aren't set in the current process are skipped.
+    /// Variables set by later calls to `env` will still be passed.  This
+    /// can be chained.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("/usr/bin/env")
+    ///     .env_inherit_only(&["TERM", "HOME", "PATH"])
+    ///     .env("LANG", "C")
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    pub fn env_inherit_only<I, K>(&mut self, keys: I) -> &mut Command
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<OsStr>,
+    {
+        self.env_clear();
+        self.env_inherit
+            .extend(keys.into_iter().map(|key| key.as_ref().to_owned()));
+        self
+    }
+
+    /// Only pass on the variables from the environment we start from for
+    /// which `f` returns true, given the name and value.  This is checked
+    /// when `exec` is called, against the current process's environment,
+    /// or whatever `env_inherit_only`, `env_capture` or `env_from` chose
+    /// instead.  Variables set by `env` are applied afterwards, so they're
+    /// always passed.  Each call adds another predicate that variables
+    /// must pass, and this can be chained.
+    ///
+    /// ```
+    /// let err = exec::Command::new("sh")
+    ///     .args(&["-c", "test -z \"$AWS_SECRET_ACCESS_KEY$API_TOKEN\" && test -n \"$PATH\""])
+    ///     .env_from(vec![
+    ///         ("PATH", "/bin:/usr/bin"),
+    ///         ("AWS_SECRET_ACCESS_KEY", "hunter2"),
+    ///         ("API_TOKEN", "hunter2"),
+    ///     ])
+    ///     .env_retain(|key, _| !key.to_string_lossy().starts_with("AWS_"))
+    ///     .env_retain(|key, _| !key.to_string_lossy().ends_with("_TOKEN"))
+    ///     .exec();
+    /// panic!("Error: {}", err);
+    /// ```
+    pub fn env_retain<F>(&mut self, f: F) -> &mut Command
+    where
+        F: FnMut(&OsStr, &OsStr) -> bool + Send + Sync + 'static,
+    {
+        self.env_retain.push(Arc::new(Mutex::new(f)));
+        self
+    }
+
+    /// Take a copy of the current process's environment now, and pass
+    /// that to the new program instead of the environment as it is when
+    /// `exec` is called.  Variables set by `env` and removed by
+    /// `env_remove` are still applied on top of the copy.  This undoes any
+    /// earlier `env_clear`, and can be chained.
+    ///
+    /// This avoids racing with other threads that change the environment
+    /// while the command is being built, since the environment is only
+    /// read once, here.
+    ///
+    /// ```
+    /// std::env::set_var("GREETING", "hello");
+    /// let mut cmd = exec::Command::new("sh");
+    /// cmd.args(&["-c", "test \"$GREETING\" = hello"]).env_capture();
+    /// std::env::set_var("GREETING", "goodbye");
+    ///
+    /// // The shell exits successfully only if it sees the old value.
+    /// let err = cmd.exec();
+    /// panic!("Error: {}", err);
+    /// ```
+    pub fn env_capture(&mut self) -> &mut Command {
+        self.env_from(env::vars_os())
+    }
+
+    /// Pass the variables in `vars` to the new program instead of our own
+    /// environment, such as one saved from another process.  This
+    /// replaces the environment the program starts from, rather than
+    /// adding to it like `envs`: variables set by `env` and removed by
+    /// `env_remove` are still applied on top of `vars`.  This undoes any
+    /// earlier `env_clear`, `env_inherit_only` or `env_capture`, and can be
+    /// chained.
+    ///
+    /// ```
+    /// let saved = vec![("GREETING", "hello"), ("LANG", "C")];
+    ///
+    /// // The shell exits successfully only if it sees exactly our changes
+    /// // on top of the saved environment.
+    /// let err = exec::Command::new("/bin/sh")
+    ///     .args(&["-c", "test \"$GREETING $LANG\" = 'hello C.UTF-8' && test -z \"$HOME\""])
+    ///     .env_from(saved)
+    ///     .env("LANG", "C.UTF-8")
+    ///     .exec();
+    /// panic!("Error: {}", err);
+    /// ```
+    pub fn env_from<I, K, V>(&mut self, vars: I) -> &mut Command
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.env_clear = false;
+        self.env_inherit.clear();
+        self.env_base = Some(
+            vars.into_iter()
+                .map(|(key, val)| (key.as_ref().to_owned(), val.as_ref().to_owned()))
+                .collect(),
+        );
+        self
+    }
+
+    /// If `enabled`, replace each `$NAME` or `${NAME}` in the arguments
+    /// with the value of that environment variable right before running
+    /// the program, or with nothing if it isn't set.  The variables are
+    /// looked up in the environment the program will get, including any
+    /// changes made with `env` and friends.  This can be chained.
+    ///
+    /// This is deliberately much simpler than what a shell does: there's no
+    /// quoting, globbing, word splitting, default values or command
+    /// substitution, and a `$` that isn't followed by a name is left as it
+    /// is.  The program name and `arg0` are never expanded.  This is off by
+    /// default, so that arguments are normally passed exactly as given.
+    ///
+    /// ```
+    /// let err = exec::Command::new("test")
+    ///     .args(&["$GREETING-${GREETING}$", "=", "hello-hello$"])
+    ///     .env("GREETING", "hello")
+    ///     .expand_env(true)
+    ///     .exec();
+    /// panic!("Error: {}", err);
+    /// ```
+    pub fn expand_env(&mut self, enabled: bool) -> &mut Command {
+        self.expand_env = enabled;
+        self
+    }
+
+    /// Change to `dir` before running the program.  If `dir` is relative,
+    /// it's interpreted relative to the current directory at the time
+    /// `exec` is called.  This can be chained.
+    ///
+    /// The directory change happens in the current process right before
+    /// it's replaced, so it's only observable if the exec then fails.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("ls")
+    ///     .current_dir("/tmp")
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Command {
+        self.current_dir = Some(dir.as_ref().to_owned());
+        self
+    }
+
+    /// Change the root directory to `dir` with `chroot`, and then change
+    /// to the new `/`, before running the program.  This can be chained.
+    ///
+    /// This is only available on Unix, and usually requires root
+    /// privileges; otherwise `exec` fails with `EPERM`.  The root is
+    /// changed before `current_dir` is applied, so a directory set there is
+    /// interpreted inside the new root, as is the search for the program
+    /// itself.  A relative `dir` is interpreted relative to the current
+    /// directory at the time `exec` is called.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("/bin/sh")
+    ///     .chroot("/srv/container")
+    ///     .current_dir("/home")
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn chroot<P: AsRef<Path>>(&mut self, dir: P) -> &mut Command {
+        self.chroot = Some(dir.as_ref().to_owned());
+        self
+    }
+
+    /// Set the real and effective user ID of the process before running
+    /// the program.  This can be chained.
+    ///
+    /// This is only available on Unix, where it's typically used by a
+    /// program running as root to drop its privileges.  If `gid` is also
+    /// set, the group ID is changed first, because that normally isn't
+    /// allowed once we've given up root.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("id")
+    ///     .gid(65534)
+    ///     .uid(65534)
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn uid(&mut self, id: u32) -> &mut Command {
+        self.uid = Some(id);
+        self
+    }
+
+    /// Set the real and effective group ID of the process before running
+    /// the program.  This can be chained.
+    ///
+    /// This is only available on Unix.  See `uid` for an example.
+    #[cfg(unix)]
+    pub fn gid(&mut self, id: u32) -> &mut Command {
+        self.gid = Some(id);
+        self
+    }
+
+    /// Set the supplementary group IDs of the process before running the
+    /// program.  An empty slice removes all supplementary groups.  This can
+    /// be chained.
+    ///
+    /// This is only available on Unix.  When dropping root privileges with
+    /// `uid` and `gid`, you almost always want to call this as well, or the
+    /// new program will keep all of root's supplementary groups.  The
+    /// groups are changed before the group and user IDs.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("id")
+    ///     .groups(&[])
+    ///     .gid(65534)
+    ///     .uid(65534)
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn groups(&mut self, groups: &[u32]) -> &mut Command {
+        self.groups = Some(groups.to_owned());
+        self
+    }
+
+    /// Connect the program's standard input.  This can be chained.
+    ///
+    /// This is only available on Unix.  Descriptors are connected with
+    /// `dup2` before anything else is done to the process.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("sort")
+    ///     .stdin(exec::Stdio::Path("input.txt".into()))
+    ///     .stdout(exec::Stdio::Path("sorted.txt".into()))
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    ///
+    /// Files opened for the standard streams don't leave any other
+    /// descriptors behind, and the streams themselves stay open across
+    /// `exec`, even when a file is opened directly as the stream because
+    /// it was closed.
+    ///
+    /// ```
+    /// use std::fs::File;
+    /// use std::os::unix::io::AsRawFd;
+    ///
+    /// // The first descriptor `open` would hand out.
+    /// let next = File::open("/dev/null").unwrap().as_raw_fd();
+    /// unsafe { libc::close(0) };
+    /// let err = exec::Command::new("sh")
+    ///     .arg("-c")
+    ///     .arg(format!("test -e /dev/fd/0 && test ! -e /dev/fd/{}", next))
+    ///     .stdin(exec::Stdio::Path("/dev/null".into()))
+    ///     .stdout(exec::Stdio::Path("/dev/null".into()))
+    ///     .exec();
+    /// panic!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn stdin(&mut self, cfg: Stdio) -> &mut Command {
+        self.stdio[0] = cfg;
+        self
+    }
+
+    /// Connect the program's standard output.  This can be chained.
+    ///
+    /// This is only available on Unix.  See `stdin` for an example.
+    #[cfg(unix)]
+    pub fn stdout(&mut self, cfg: Stdio) -> &mut Command {
+        self.stdio[1] = cfg;
+        self
+    }
+
+    /// Connect the program's standard error.  This can be chained.
+    ///
+    /// This is only available on Unix.  Passing `Stdio::Fd(1)` sends it
+    /// wherever standard output goes, like `2>&1` in the shell.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("make")
+    ///     .stdout(exec::Stdio::PathWithFlags(
+    ///         "build.log".into(),
+    ///         libc::O_WRONLY | libc::O_APPEND | libc::O_CREAT,
+    ///     ))
+    ///     .stderr(exec::Stdio::Fd(1))
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn stderr(&mut self, cfg: Stdio) -> &mut Command {
+        self.stdio[2] = cfg;
+        self
+    }
+
+    /// Close every open file descriptor numbered `lowest` or higher before
+    /// running the program, so that it doesn't inherit our open files and
+    /// sockets.  Pass `3` to close everything except standard input,
+    /// output, and error.  Descriptors listed in `keep_fds` are left open.
+    /// This can be chained.
+    ///
+    /// This is only available on Unix.  On Linux 5.9 and later, this is a
+    /// single `close_range` call.  Elsewhere, we close each descriptor
+    /// listed in `/proc/self/fd`, or failing that, every possible
+    /// descriptor number up to the limit given by `sysconf`.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("untrusted-helper")
+    ///     .close_fds_from(3)
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    ///
+    /// With `keep_fds`, only the descriptors it lists survive:
+    ///
+    /// ```
+    /// use std::os::unix::io::AsRawFd;
+    ///
+    /// let kept = std::fs::File::open("/dev/null").unwrap();
+    /// let closed = std::fs::File::open("/dev/null").unwrap();
+    /// let script = format!(
+    ///     "test -e /dev/fd/{} && ! test -e /dev/fd/{}",
+    ///     kept.as_raw_fd(),
+    ///     closed.as_raw_fd()
+    /// );
+    /// let err = exec::Command::new("sh")
+    ///     .args(&["-c", &script])
+    ///     .keep_fds(&[kept.as_raw_fd()])
+    ///     .close_fds_from(3)
+    ///     .exec();
+    /// panic!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn close_fds_from(&mut self, lowest: RawFd) -> &mut Command {
+        self.close_fds_from = Some(lowest);
+        self
+    }
+
+    /// Let the program inherit only the file descriptors in `fds`, plus
+    /// standard input, output and error.  Before running the program,
+    /// every other open descriptor is marked close-on-exec, and those in
+    /// `fds` have the flag cleared.  Descriptors from earlier calls are kept
+    /// as well.  This can be chained.
+    ///
+    /// This is only available on Unix.  Unlike `close_fds_from`, nothing is
+    /// closed in the current process, so a descriptor can't be reused by
+    /// another thread while it's still in use, and everything is still open
+    /// if the exec fails.
+    ///
+    /// ```
+    /// use std::os::unix::io::AsRawFd;
+    ///
+    /// let file = std::fs::File::open("/dev/null").unwrap();
+    /// let fd = file.as_raw_fd();
+    ///
+    /// // The shell exits successfully only if it inherits `fd`, which
+    /// // `std` opened close-on-exec.
+    /// let err = exec::Command::new("sh")
+    ///     .args(&["-c", &format!("test -e /dev/fd/{}", fd)])
+    ///     .keep_fds(&[fd])
+    ///     .exec();
+    /// panic!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn keep_fds(&mut self, fds: &[RawFd]) -> &mut Command {
+        self.keep_fds
+            .get_or_insert_with(|| vec![0, 1, 2])
+            .extend_from_slice(fds);
+        self
+    }
+
+    /// Write a record of the `argv` the program will see to `fd` as the
+    /// very last step before it's run, for an audit trail of everything
+    /// we exec.  Each argument is followed by a NUL byte, and the record
+    /// ends with a newline.  This can be chained.
+    ///
+    /// This is only available on Unix.  The record is written with raw
+    /// `write` calls rather than through a buffer, since nothing would be
+    /// left to flush it, and partial writes are retried.  If it can't be
+    /// written, `exec` fails instead of running the program unrecorded.
+    /// `fd` must still be open at that point, so don't let
+    /// `close_fds_from` close it.  Once the record is written, `fd` is
+    /// marked close-on-exec so the program doesn't inherit it, unless it's
+    /// one of the standard streams or listed in `keep_fds`.
+    ///
+    /// ```
+    /// use std::fs::{self, File};
+    /// use std::os::unix::io::AsRawFd;
+    ///
+    /// let dir = std::env::temp_dir();
+    /// let log = dir.join("exec-audit-doctest.log");
+    /// let expected = dir.join("exec-audit-doctest.expected");
+    /// let file = File::create(&log).unwrap();
+    /// let argv = ["cmp", log.to_str().unwrap(), expected.to_str().unwrap()];
+    /// fs::write(&expected, format!("{}\0{}\0{}\0\n", argv[0], argv[1], argv[2])).unwrap();
+    ///
+    /// // `cmp` succeeds only if it was told about itself.
+    /// let err = exec::Command::new(argv[0])
+    ///     .args(&argv[1..])
+    ///     .audit_fd(file.as_raw_fd())
+    ///     .exec();
+    /// panic!("Error: {}", err);
+    /// ```
+    ///
+    /// The program doesn't get the descriptor:
+    ///
+    /// ```
+    /// use std::fs::File;
+    /// use std::os::unix::io::AsRawFd;
+    ///
+    /// let file = File::create(std::env::temp_dir().join("exec-audit-leak.log")).unwrap();
+    /// let fd = file.as_raw_fd();
+    /// // Make it inheritable, like a descriptor from outside Rust might be.
+    /// unsafe { libc::fcntl(fd, libc::F_SETFD, 0) };
+    /// let err = exec::Command::new("sh")
+    ///     .arg("-c")
+    ///     .arg(format!("test ! -e /dev/fd/{}", fd))
+    ///     .audit_fd(fd)
+    ///     .exec();
+    /// panic!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn audit_fd(&mut self, fd: RawFd) -> &mut Command {
+        self.audit_fd = Some(fd);
+        self
+    }
+
+    /// Start a new session before running the program, making the process
+    /// the leader of a new session and process group.  This can be
+    /// chained.
+    ///
+    /// This is only available on Unix.  After `setsid`, the process no
+    /// longer has a controlling terminal, so the program won't receive
+    /// signals from the terminal it was started from, and opening
+    /// `/dev/tty` will fail.  `setsid` fails with `EPERM` if the process is
+    /// already a process group leader, which is often the case for a
+    /// program started directly from an interactive shell.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("my-daemon")
+    ///     .setsid()
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn setsid(&mut self) -> &mut Command {
+        self.setsid = true;
+        self
+    }
+
+    /// Detach the program from the terminal and session it was started
+    /// from, the way a daemon does: give up the controlling terminal with
+    /// `TIOCNOTTY`, start a new session as with `setsid`, and connect any
+    /// standard streams that haven't been redirected to `/dev/null`.  This
+    /// can be chained.
+    ///
+    /// This is only available on Unix.  It's the usual daemon prelude,
+    /// minus the `fork`, so the same `EPERM` caveat as `setsid` applies.
+    /// Streams redirected by later calls to `stdin`, `stdout` or `stderr`
+    /// go where those calls say.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("my-daemon")
+    ///     .detach()
+    ///     .stderr(exec::Stdio::Path("/var/log/my-daemon.log".into()))
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn detach(&mut self) -> &mut Command {
+        self.detach = true;
+        self.setsid = true;
+        for stdio in &mut self.stdio {
+            if *stdio == Stdio::Inherit {
+                *stdio = Stdio::Path(PathBuf::from("/dev/null"));
+            }
+        }
+        self
+    }
+
+    /// Make the terminal open as `fd` the controlling terminal of the
+    /// program, with `ioctl(fd, TIOCSCTTY, 0)`, as terminal emulators and
+    /// multiplexers do for the pseudoterminals they create.  This can be
+    /// chained.
+    ///
+    /// This is only available on Unix.  Only a session leader without a
+    /// controlling terminal can acquire one, so this is usually combined
+    /// with `setsid`, and is done right after the new session starts.  The
+    /// standard streams have already been redirected by then, so if you
+    /// passed the terminal to `stdin` as a descriptor above 2, which is
+    /// closed after being duplicated, use 0 here instead.  If the `ioctl`
+    /// fails, `exec` returns `Error::Errno`.  `spawn_replace` can't do this,
+    /// and fails with `ENOTSUP`.
+    ///
+    /// ```no_run
+    /// # let pty: std::os::unix::io::RawFd = 3;
+    /// let err = exec::Command::new("bash")
+    ///     .stdin(exec::Stdio::Fd(pty))
+    ///     .stdout(exec::Stdio::Fd(0))
+    ///     .stderr(exec::Stdio::Fd(0))
+    ///     .setsid()
+    ///     .controlling_tty(0)
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn controlling_tty(&mut self, fd: RawFd) -> &mut Command {
+        self.controlling_tty = Some(fd);
+        self
+    }
+
+    /// Run the program on the terminal open as `slave_fd`, usually the
+    /// slave side of a pseudoterminal we allocated: start a new session,
+    /// connect standard input, output and error to the terminal, make it
+    /// the controlling terminal, and close `slave_fd` itself if it isn't
+    /// one of the standard streams.  This is the same sequence as
+    /// `login_tty`, made of `stdin`, `stdout`, `stderr`, `setsid` and
+    /// `controlling_tty`, so later calls to those can still change
+    /// parts of it.  This can be chained.
+    ///
+    /// This is only available on Unix.  Any step that fails makes `exec`
+    /// return `Error::Errno`.
+    ///
+    /// ```
+    /// let (mut master, mut slave) = (0, 0);
+    /// let res = unsafe {
+    ///     libc::openpty(
+    ///         &mut master,
+    ///         &mut slave,
+    ///         std::ptr::null_mut(),
+    ///         std::ptr::null(),
+    ///         std::ptr::null(),
+    ///     )
+    /// };
+    /// assert_eq!(res, 0);
+    /// // `/dev/tty` can only be opened by a process with a controlling
+    /// // terminal.
+    /// let err = exec::Command::new("sh")
+    ///     .args(&["-c", "test -t 0 && test -t 1 && test -t 2 && : < /dev/tty"])
+    ///     .use_pty(slave)
+    ///     .exec();
+    /// panic!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn use_pty(&mut self, slave_fd: RawFd) -> &mut Command {
+        self.stdio = [
+            Stdio::Fd(slave_fd),
+            Stdio::Fd(slave_fd),
+            Stdio::Fd(slave_fd),
+        ];
+        self.setsid = true;
+        // The standard streams are connected first, so by then the
+        // terminal is also standard input.
+        self.controlling_tty = Some(0);
+        self
+    }
+
+    /// Move the process into the process group `pgid` before running the
+    /// program, or into a new process group of its own if `pgid` is 0.
+    /// This can be chained.
+    ///
+    /// This is only available on Unix, and works like
+    /// `std::os::unix::process::CommandExt::process_group`.  It's done with
+    /// `setpgid(0, pgid)`, so the usual restrictions apply: the group must
+    /// be in the same session, and a session leader can't change groups.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("sleep")
+    ///     .arg("100")
+    ///     .process_group(0)
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn process_group(&mut self, pgid: i32) -> &mut Command {
+        self.process_group = Some(pgid);
+        self
+    }
+
+    /// Set the file mode creation mask before running the program, so
+    /// that the files it creates get predictable permissions.  This can be
+    /// chained.
+    ///
+    /// This is only available on Unix.  Setting the mask can't fail, but
+    /// like the other process settings, it's applied to the current
+    /// process right before it's replaced.  If the exec then fails, the
+    /// current process keeps the new mask.
+    ///
+    /// ```
+    /// // The shell exits successfully only if it sees the new mask.
+    /// let err = exec::Command::new("sh")
+    ///     .args(&["-c", "test $(umask) = 0027"])
+    ///     .umask(0o027)
+    ///     .exec();
+    /// panic!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn umask(&mut self, mask: u32) -> &mut Command {
+        self.umask = Some(mask);
+        self
+    }
+
+    /// Set the soft and hard limits for `resource`, one of the
+    /// `libc::RLIMIT_*` constants, before running the program.  Each
+    /// resource can be limited separately, and a later call for the same
+    /// resource replaces the earlier limits.  This can be chained.
+    ///
+    /// This is only available on Unix.  The limits are applied to the
+    /// current process with `setrlimit` right before it's replaced, so
+    /// they stay in effect if the exec then fails.  Raising a hard limit
+    /// usually requires privileges, and fails with `EPERM` otherwise.
+    ///
+    /// ```
+    /// extern crate libc;
+    ///
+    /// // The shell exits successfully only if it sees the new limit.
+    /// let err = exec::Command::new("sh")
+    ///     .args(&["-c", "test $(ulimit -n) = 64"])
+    ///     .rlimit(libc::RLIMIT_NOFILE as i32, 64, 64)
+    ///     .exec();
+    /// panic!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn rlimit(&mut self, resource: i32, soft: u64, hard: u64) -> &mut Command {
+        self.rlimits.retain(|&(r, _, _)| r != resource);
+        self.rlimits.push((resource, soft, hard));
+        self
+    }
+
+    /// Add `adjustment` to the scheduling priority with `nice` before
+    /// running the program.  Positive values make the program run at a
+    /// lower priority.  A later call replaces the earlier adjustment.
+    /// This can be chained.
+    ///
+    /// This is only available on Unix.  Lowering the niceness value,
+    /// i.e. raising the priority, usually requires privileges, and fails
+    /// with `EPERM` otherwise.  Like the other process settings, it's
+    /// applied to the current process right before it's replaced.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("make")
+    ///     .nice(10)
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn nice(&mut self, adjustment: i32) -> &mut Command {
+        self.nice = Some(adjustment);
+        self
+    }
+
+    /// Reset the disposition of every signal to the default, and unblock
+    /// all signals, before running the program.  This can be chained.
+    ///
+    /// This is only available on Unix.  A program inherits the signal mask
+    /// and any ignored signals from the process that runs it, and many
+    /// programs misbehave if they start with signals ignored or blocked.
+    /// This is easy to run into if we're running inside an async runtime
+    /// or anything else that changes how signals are handled, and Rust
+    /// itself ignores `SIGPIPE`.  Signal handlers are always reset by exec,
+    /// so this only makes a difference for ignored and blocked signals.
+    /// `SIGKILL` and `SIGSTOP` can never be caught or ignored, so they're
+    /// always left alone.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("sh")
+    ///     .args(&["-c", "yes | head -n 1"])
+    ///     .reset_signals()
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn reset_signals(&mut self) -> &mut Command {
+        self.reset_signals = true;
+        self
+    }
+
+    /// Reset `SIGPIPE` to its default disposition before running the
+    /// program.  This can be chained.
+    ///
+    /// This is only available on Unix.  The Rust runtime ignores `SIGPIPE`
+    /// at startup, and programs we run inherit that.  Most command-line
+    /// tools expect to be killed by `SIGPIPE` when whatever is reading
+    /// their output goes away, and without it, a pipeline like `yes | head`
+    /// may print errors or never finish.  This is a cheaper, more targeted
+    /// version of `reset_signals`.
+    ///
+    /// ```
+    /// // `yes` stops as soon as `head` exits, and then we check that a
+    /// // child of the shell really is killed by `SIGPIPE`.
+    /// let err = exec::Command::new("sh")
+    ///     .args(&["-c", "yes | head -n 1 >/dev/null && sh -c 'kill -PIPE $$'; test $? -gt 128"])
+    ///     .restore_sigpipe()
+    ///     .exec();
+    /// panic!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn restore_sigpipe(&mut self) -> &mut Command {
+        self.restore_sigpipe = true;
+        self
+    }
+
+    /// Reset the disposition of signal `sig` to the default before running
+    /// the program.  Each call adds another signal, and this can be
+    /// chained.  If `sig` isn't a signal that can be reset, `exec` fails
+    /// with `EINVAL`.
+    ///
+    /// This is only available on Unix, and is a targeted version of
+    /// `reset_signals` for when only some dispositions matter, and the
+    /// signal mask should be left alone.  For example, a runtime that
+    /// ignores `SIGCHLD` to have its children reaped automatically leaves
+    /// the program unable to wait for its own.
+    ///
+    /// ```
+    /// // Ignored signals stay ignored across exec, so only the reset lets
+    /// // `SIGUSR1` kill a child of the shell.
+    /// unsafe { libc::signal(libc::SIGUSR1, libc::SIG_IGN) };
+    /// let err = exec::Command::new("sh")
+    ///     .args(&["-c", "sh -c 'kill -USR1 $$'; test $? -gt 128"])
+    ///     .reset_signal(libc::SIGCHLD)
+    ///     .reset_signal(libc::SIGUSR1)
+    ///     .exec();
+    /// panic!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn reset_signal(&mut self, sig: i32) -> &mut Command {
+        self.default_signals.push(sig);
+        self
+    }
+
+    /// If `enabled`, a program that can't be run because it isn't in a
+    /// format the system recognizes (`ENOEXEC`) is assumed to be a shell
+    /// script without a `#!` line, and is run with `/bin/sh` instead, the
+    /// way shells do.  This is off by default.  This can be chained.
+    ///
+    /// This is only available on Unix.  Some C libraries already do this
+    /// when searching `PATH`, but this also covers `program_path`.
+    ///
+    /// ```
+    /// use std::fs;
+    /// use std::os::unix::fs::PermissionsExt;
+    ///
+    /// let script = std::env::temp_dir().join("exec-shell-fallback-doctest");
+    /// fs::write(&script, "test \"$1\" = hello\n").unwrap();
+    /// fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    ///
+    /// // The script exits successfully only if the shell runs it.
+    /// let err = exec::Command::new("script")
+    ///     .program_path(&script)
+    ///     .arg("hello")
+    ///     .shell_fallback(true)
+    ///     .exec();
+    /// panic!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn shell_fallback(&mut self, enabled: bool) -> &mut Command {
+        self.shell_fallback = enabled;
+        self
+    }
+
+    /// Register a closure to be called right before the program is run.
+    /// If it returns an error, the program isn't run and `exec` returns
+    /// `Error::PreExec`.  Closures are called in the order they were
+    /// registered, after all the other changes to the process requested
+    /// from this builder have been made.  This can be chained.
+    ///
+    /// ```no_run
+    /// let mut cmd = exec::Command::new("ls");
+    /// unsafe {
+    ///     cmd.pre_exec(|| {
+    ///         // Detach from the parent's session.
+    ///         if libc::setsid() == -1 {
+    ///             return Err(std::io::Error::last_os_error());
+    ///         }
+    ///         Ok(())
+    ///     });
+    /// }
+    /// let err = cmd.exec();
+    /// println!("Error: {}", err);
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// Unlike `std::os::unix::process::CommandExt::pre_exec`, there's no
+    /// `fork` here: the closure runs in the current process, which keeps
+    /// running if the exec fails.  But callers often use this crate in
+    /// the child of a `fork`, and in a multithreaded program the only
+    /// things that may safely be done there are the async-signal-safe
+    /// operations listed in `signal-safety(7)`.  In particular, the
+    /// closure must not allocate memory, take locks, or use anything (like
+    /// `println!`) that might, because another thread may have held that
+    /// lock at the moment of the `fork`.
+    ///
+    /// The closure also must not invalidate anything the exec needs, such
+    /// as by closing file descriptors that the new program expects to
+    /// inherit.
+    pub unsafe fn pre_exec<F>(&mut self, f: F) -> &mut Command
+    where
+        F: FnMut() -> io::Result<()> + Send + Sync + 'static,
+    {
+        self.pre_exec.push(Arc::new(Mutex::new(f)));
+        self
+    }
+
+    /// Register a closure to be called with the path of the program and
+    /// the `argv` it will see, as the very last step before it's run.
+    /// This is the place to log what we're about to become, since `exec`
+    /// won't return if it succeeds.  A later call replaces the closure.
+    /// This can be chained.
+    ///
+    /// When `PATH` is searched, the path passed is the one the search is
+    /// expected to find; if it finds nothing, the name passed to `new` is
+    /// passed instead.  The closure is only called once, even if the exec
+    /// fails and is retried, and it's shared with any clones of this
+    /// command.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("echo")
+    ///     .arg("hello")
+    ///     .on_exec(|program, argv| eprintln!("running {:?} as {:?}", program, argv))
+    ///     .exec();
+    /// println!("Error: {}", err);
+    /// ```
+    pub fn on_exec<F>(&mut self, f: F) -> &mut Command
+    where
+        F: FnOnce(&OsStr, &[OsString]) + Send + 'static,
+    {
+        self.on_exec = Some(Arc::new(Mutex::new(Some(Box::new(f)))));
+        self
+    }
+
+    /// Return the program that will be run, as passed to `new`.  This
+    /// isn't affected by `arg0`.
+    ///
+    /// ```
+    /// let mut cmd = exec::Command::new("echo");
+    /// cmd.arg0("greet");
+    /// assert_eq!(cmd.get_program(), "echo");
+    /// ```
+    pub fn get_program(&self) -> &OsStr {
+        &self.argv[0]
+    }
+
+    /// Return the arguments that will be passed to the program, not
+    /// including `argv[0]`.
+    ///
+    /// ```
+    /// let mut cmd = exec::Command::new("echo");
+    /// cmd.args(&["hello", "world"]);
+    /// let args: Vec<_> = cmd.get_args().collect();
+    /// assert_eq!(args, &["hello", "world"]);
+    /// ```
+    ///
+    /// Arguments are kept exactly as they were given, even on Windows,
+    /// where they might not be valid UTF-16, and they can still be passed
+    /// to the program:
+    ///
+    /// ```
+    /// #[cfg(windows)]
+    /// {
+    ///     use std::ffi::OsString;
+    ///     use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    ///
+    ///     // Unpaired surrogates, which no `String` can hold.
+    ///     let wide = [0xD800, u16::from(b' '), 0xDC00, u16::from(b'"')];
+    ///     let mut cmd = exec::Command::new("cmd");
+    ///     cmd.arg(OsString::from_wide(&wide));
+    ///     assert_eq!(cmd.validate(), Ok(()));
+    ///     let args: Vec<Vec<u16>> = cmd.get_args().map(|arg| arg.encode_wide().collect()).collect();
+    ///     assert_eq!(args, [wide.to_vec()]);
+    /// }
+    /// ```
+    pub fn get_args(&self) -> impl Iterator<Item = &OsStr> {
+        self.argv[1..].iter().map(|arg| arg.as_os_str())
+    }
+
+    /// Return the environment variables explicitly set or removed with
+    /// `env`, `envs` and `env_remove`, sorted by name.  A value of `None`
+    /// means the variable will be removed.  Variables that are simply
+    /// inherited aren't included.
+    ///
+    /// ```
+    /// use std::ffi::OsStr;
+    ///
+    /// let mut cmd = exec::Command::new("ls");
+    /// cmd.env("TZ", "UTC").env_remove("LD_PRELOAD");
+    /// let envs: Vec<_> = cmd.get_envs().collect();
+    /// assert_eq!(
+    ///     envs,
+    ///     &[(OsStr::new("LD_PRELOAD"), None), (OsStr::new("TZ"), Some(OsStr::new("UTC")))]
+    /// );
+    /// ```
+    pub fn get_envs(&self) -> impl Iterator<Item = (&OsStr, Option<&OsStr>)> {
+        self.env_vars
+            .iter()
+            .map(|(key, val)| (key.as_os_str(), val.as_ref().map(|val| val.as_os_str())))
+    }
+
+    /// Return the directory set with `current_dir`, if any.
+    ///
+    /// ```
+    /// use std::path::Path;
+    ///
+    /// let mut cmd = exec::Command::new("ls");
+    /// assert_eq!(cmd.get_current_dir(), None);
+    /// cmd.current_dir("/tmp");
+    /// assert_eq!(cmd.get_current_dir(), Some(Path::new("/tmp")));
+    /// ```
+    pub fn get_current_dir(&self) -> Option<&Path> {
+        self.current_dir.as_deref()
+    }
+
+    /// Find the executable that `exec` would run, without running it, or
+    /// `None` if there isn't one.  If `program_path` was set, that's
+    /// checked as is; otherwise `PATH` is searched for the program passed
+    /// to `new`, using the same rules as `resolve_in_path`.
+    ///
+    /// The search happens in the current process as it is now, so it
+    /// doesn't take `chroot` or a relative `current_dir` into account.
+    ///
+    /// ```
+    /// let cmd = exec::Command::new("sh");
+    /// let path = cmd.get_resolved_program().expect("sh not found");
+    /// assert!(path.ends_with("sh"));
+    /// assert!(exec::Command::new("no-such-program").get_resolved_program().is_none());
+    /// ```
+    pub fn get_resolved_program(&self) -> Option<PathBuf> {
+        match (&self.program_path, &self.search_path) {
+            (Some(path), _) => find_executable(Path::new(path)),
+            (None, Some(dirs)) => resolve_in(&self.argv[0], Some(dirs)),
+            (None, None) => resolve_in_path(&self.argv[0]),
+        }
+    }
+
+    /// Compute the complete environment the new program would get if
+    /// `exec` were called now, as `KEY=VALUE` pairs in the same order they
+    /// would be passed to it.  Unlike `get_envs`, this includes inherited
+    /// variables, and reflects `env_clear`, `env_inherit_only`,
+    /// `env_retain` and the rest.  This is handy for logging exactly what a
+    /// program was run with.
+    ///
+    /// ```
+    /// use std::ffi::OsString;
+    ///
+    /// let mut cmd = exec::Command::new("env");
+    /// cmd.env_clear().env("LANG", "C").env("TERM", "dumb").env_remove("TERM");
+    /// assert_eq!(cmd.env_snapshot(), vec![(OsString::from("LANG"), OsString::from("C"))]);
+    /// ```
+    pub fn env_snapshot(&self) -> Vec<(OsString, OsString)> {
+        self.build_env().unwrap_or_else(|| env::vars_os().collect())
+    }
+
+    /// Convert the `argv` array the new program will see to a
+    /// `CStringArray`, for use with your own calls into the C library.
+    /// This includes `arg0`.  Fails if any argument contains a NUL byte.
+    ///
+    /// This is only available on Unix.
+    #[cfg(unix)]
+    pub fn build_c_argv(&self) -> Result<CStringArray, Error> {
+        self.mark_used();
+        CStringArray::from_args(self.exec_argv()).map(CStringArray::into_heap)
+    }
+
+    /// Convert the environment the new program will see to a
+    /// `CStringArray` of `KEY=VALUE` strings, for use with your own calls
+    /// into the C library.  Returns `None` if nothing about the environment
+    /// has been changed, so the program should just inherit ours.  Fails if
+    /// a name contains `=` or any variable contains a NUL byte.
+    ///
+    /// This is only available on Unix.
+    ///
+    /// ```
+    /// let mut cmd = exec::Command::new("env");
+    /// assert!(cmd.build_c_envp().unwrap().is_none());
+    /// cmd.env_clear().env("LANG", "C");
+    /// let envp = cmd.build_c_envp().unwrap().unwrap();
+    /// assert_eq!(envp.iter().next().unwrap().to_bytes(), b"LANG=C");
+    /// ```
+    #[cfg(unix)]
+    pub fn build_c_envp(&self) -> Result<Option<CStringArray>, Error> {
+        self.mark_used();
+        match self.build_env() {
+            Some(env) => CStringArray::from_env(env).map(|envp| Some(envp.into_heap())),
+            None => Ok(None),
+        }
+    }
+
+    /// Check that the program, its arguments, its environment and any paths
+    /// we were given can all be passed to the system, without running
+    /// anything or changing the current process.  This returns the first
+    /// error that `exec` would find before it started making changes.
+    ///
+    /// This is useful for rejecting a bad command up front, such as
+    /// before a `fork`, where an error is much harder to report.  Errors
+    /// from the system itself, like a missing program, can still only be
+    /// found by running it.
+    ///
+    /// ```
+    /// let mut cmd = exec::Command::new("echo");
+    /// cmd.arg("hello");
+    /// assert_eq!(cmd.validate(), Ok(()));
+    /// cmd.arg("nul\0byte");
+    /// assert!(matches!(
+    ///     cmd.validate(),
+    ///     Err(exec::Error::NullByteInArgument { index: Some(2), .. })
+    /// ));
+    /// ```
+    pub fn validate(&self) -> Result<(), Error> {
+        #[cfg(unix)]
+        let (to_native, env_native) = (to_cstring, env_cstring);
+        #[cfg(windows)]
+        let (to_native, env_native) = (to_wide, env_wide);
+
+        self.mark_used();
+        self.check_env()?;
+        self.check_absolute_path()?;
+        to_native(self.program_path.as_ref().unwrap_or(&self.argv[0]))?;
+        for (i, arg) in self.exec_argv().into_iter().enumerate() {
+            to_native(&arg).map_err(|err| err.at_index(i))?;
+        }
+        if let Some(env) = self.build_env() {
+            for (key, val) in env {
+                env_native(&key, &val)?;
+            }
+        }
+        if let Some(ref dir) = self.current_dir {
+            to_native(dir.as_os_str())?;
+        }
+        #[cfg(unix)]
+        {
+            if let Some(ref dir) = self.chroot {
+                to_native(dir.as_os_str())?;
+            }
+            for stdio in &self.stdio {
+                match stdio {
+                    Stdio::Path(path) | Stdio::PathWithFlags(path, _) => {
+                        to_native(path.as_os_str())?;
+                    }
+                    Stdio::Inherit | Stdio::Fd(_) => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Execute the command we built.  If this function succeeds, it will
+    /// never return.
+    ///
+    /// The settings are applied to the current process in a fixed order,
+    /// whatever order the builder methods were called in:
+    ///
+    /// 1. Everything is checked and converted, so most mistakes are
+    ///    reported before the process has been changed at all.
+    /// 2. The standard streams are connected, so a `Stdio::Path` is opened
+    ///    relative to the original root and current directory, and then
+    ///    descriptors are closed or kept with `close_fds_from` and
+    ///    `keep_fds`.
+    /// 3. `detach`, `setsid`, `controlling_tty` and `process_group`.
+    /// 4. `chroot`, followed by a change to the new `/`.
+    /// 5. `current_dir`, which is therefore inside the new root.
+    /// 6. `umask`, `rlimit`, `nice` and the signal settings.
+    /// 7. `groups`, `gid` and `uid`, in that order, so that privileges are
+    ///    dropped only after everything that might need them.
+    /// 8. The `pre_exec` closures, which see the final directory and
+    ///    user.
+    /// 9. The search for the program, if it was given `with_path`.
+    /// 10. The `on_exec` hook, the record written to `audit_fd`, and
+    ///     finally the `cleanup` passed to `exec_after`.
+    ///
+    /// On Windows, only `current_dir` and the steps after it apply.
+    ///
+    /// This allocates memory before the program is run, so it isn't safe
+    /// to call in the child of a `fork` in a multithreaded program; use
+    /// `exec_signal_safe` there instead.  On Unix, it allocates:
+    ///
+    /// - the list of arguments, which are borrowed from the builder unless
+    ///   `expand_env` has to expand them;
+    /// - a copy of our whole environment, if the program's environment was
+    ///   changed in any way;
+    /// - a `CString` for the program, and for the arguments and any changed
+    ///   environment, a single buffer holding all of the strings, with the
+    ///   array of pointers to them kept inline unless there are more than
+    ///   seven;
+    /// - with `with_path`, the strings converted once more while they're
+    ///   checked before setup, and each candidate path while searching;
+    /// - while setting up, a `CString` for `chroot` and any `Stdio::Path`,
+    ///   the list of descriptors to close, the `argv` passed to the
+    ///   `on_exec` hook and the record written to `audit_fd`;
+    /// - where the C library has no `execvpe`, a `CString` for each
+    ///   candidate while searching `PATH`.
+    pub fn exec(&mut self) -> Error {
+        self.exec_ref()
+    }
+
+    /// Like `exec`, but only needs a shared reference, so a prepared
+    /// `Command` can be run from an immutable configuration or an `Arc`.
+    /// Nothing about the builder changes when it's run, and `exec` only
+    /// takes `&mut self` for compatibility.
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    ///
+    /// let mut cmd = exec::Command::new("true");
+    /// cmd.arg("ignored");
+    /// let shared = Arc::new(cmd);
+    /// let err = shared.exec_ref();
+    /// panic!("Error: {}", err);
+    /// ```
+    pub fn exec_ref(&self) -> Error {
+        self.exec_after(|| ())
+    }
+
+    /// Like `exec`, but call `cleanup` right before the current process is
+    /// replaced.  Since the `Drop` implementations of our values never run
+    /// once the process has been replaced, this is the place to release
+    /// anything that must not outlive us, like lock files or temporary
+    /// directories.
+    ///
+    /// `cleanup` runs after everything else: the settings on this command,
+    /// the `pre_exec` closures, the `on_exec` hook and the record written
+    /// to `audit_fd`, in that order.
+    /// Unlike `pre_exec`, it can't fail, and it's only called if nothing
+    /// before it did, so if this function returns an error from an earlier
+    /// step, the resources are still held and will be dropped normally.
+    ///
+    /// ```
+    /// let lock = std::env::temp_dir().join("exec-after-doctest.lock");
+    /// std::fs::write(&lock, b"").unwrap();
+    /// let err = exec::Command::new("test")
+    ///     .arg("!")
+    ///     .arg("-e")
+    ///     .arg(&lock)
+    ///     .exec_after(|| std::fs::remove_file(&lock).unwrap());
+    /// panic!("Error: {}", err);
+    /// ```
+    pub fn exec_after<F: FnOnce()>(&self, cleanup: F) -> Error {
+        self.mark_used();
+        exec_try!(self.check_env());
+        let search_late = self.searches_after_setup();
+        if search_late {
+            exec_try!(self.validate());
+            dry_run!();
+            exec_try!(self.setup());
+        }
+        let (program, search_path) = exec_try!(self.program());
+        let program = &program;
+        let env = self.build_env();
+        #[cfg(unix)]
+        let argv = self.exec_argv();
+        #[cfg(windows)]
+        let argv = self.quoted_argv();
+        let err = exec_env(program, &argv, env.as_ref(), search_path, || {
+            if !search_late {
+                self.setup()?;
+            }
+            self.call_on_exec(program, search_path);
+            #[cfg(unix)]
+            self.write_audit_record(&argv)?;
+            cleanup();
+            Ok(())
+        });
+        #[cfg(unix)]
+        {
+            if self.shell_fallback && err.raw_os_error() == Some(libc::ENOEXEC) {
+                let script = if search_path {
+                    resolve_in_path(program)
+                } else {
+                    Some(PathBuf::from(program))
+                };
+                if let Some(script) = script {
+                    let argv = iter::once(OsStr::new(SHELL))
+                        .chain(iter::once(script.as_os_str()))
+                        .chain(argv[1..].iter().map(|arg| arg.as_ref()));
+                    // Our settings are already in effect, and mustn't be
+                    // applied twice.
+                    return exec_env(SHELL, argv, env.as_ref(), false, || Ok(()));
+                }
+            }
+        }
+        err
+    }
+
+    /// Like `exec`, but find the program inside `dir`, a capability for a
+    /// directory from the `cap-std` crate, instead of on the ambient
+    /// filesystem.  The program's name is opened relative to `dir`, and
+    /// the open file is run with `fexecve`.  `PATH` isn't searched, and
+    /// `program_path`, if it's set, is opened relative to `dir` as well.
+    ///
+    /// This is only available with the `cap-std` feature, on platforms
+    /// that have `fexecve`.  As with the rest of `cap-std`, absolute names
+    /// and `..` components that would leave `dir` fail instead of being
+    /// followed.  The program must be readable as well as executable, and
+    /// since the file is opened close-on-exec, scripts with a `#!` line
+    /// won't run; see `fexecve`.  The other settings, like `current_dir`
+    /// and `chroot`, still apply, after the program has been opened, and
+    /// `close_fds_from` leaves the program's descriptor open until it's
+    /// run.
+    ///
+    /// ```
+    /// let bin = cap_std::fs::Dir::open_ambient_dir("/bin", cap_std::ambient_authority()).unwrap();
+    /// let err = exec::Command::new("sh")
+    ///     .args(&["-c", "exit 0"])
+    ///     .close_fds_from(3)
+    ///     .exec_at(&bin);
+    /// panic!("Error: {}", err);
+    /// ```
+    ///
+    /// A name that would lead outside `dir` fails with `Error::OutsideDir`:
+    ///
+    /// ```
+    /// let bin = cap_std::fs::Dir::open_ambient_dir("/bin", cap_std::ambient_authority()).unwrap();
+    /// for name in &["/bin/sh", "../bin/sh", "../../bin/sh"] {
+    ///     let err = exec::Command::new(name).exec_at(&bin);
+    ///     assert!(matches!(err, exec::Error::OutsideDir(_)), "{}: {}", name, err);
+    /// }
+    /// ```
+    #[cfg(all(
+        feature = "cap-std",
+        any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "dragonfly"
+        )
+    ))]
+    pub fn exec_at(&mut self, dir: &cap_std::fs::Dir) -> Error {
+        use std::os::unix::io::AsRawFd;
+
+        self.mark_used();
+        exec_try!(self.check_env());
+        let program = self.program_path.as_ref().unwrap_or(&self.argv[0]);
+        let argv = self.exec_argv();
+        let c_argv = exec_try!(CStringArray::from_args(&argv));
+        let envp = match self.build_env() {
+            Some(env) => exec_try!(CStringArray::from_env(env)),
+            None => exec_try!(CStringArray::from_env(env::vars_os())),
+        };
+        dry_run!();
+        // `cap-std` reports the paths it refuses to follow without an OS
+        // error code.
+        let file = exec_try!(dir.open(program).map_err(|err| match err.raw_os_error() {
+            Some(code) => Error::Errno(Errno(code)),
+            None => Error::OutsideDir(err),
+        }));
+        exec_try!(self.setup_for(false, Some(file.as_raw_fd())));
+        self.call_on_exec(program, false);
+        exec_try!(self.write_audit_record(&argv));
+        sys_fexecve(file.as_raw_fd(), &c_argv, &envp)
+    }
+
+    /// Like `exec`, but returns the error as a `Result` so that it can be
+    /// propagated with `?`.  Since `exec` only ever returns on failure,
+    /// the `Ok` value is `Infallible` and can never actually occur.
+    ///
+    /// ```no_run
+    /// use std::convert::{Infallible, TryFrom};
+    ///
+    /// fn run() -> Result<Infallible, Box<dyn std::error::Error>> {
+    ///     let never = exec::Command::new("echo").arg("hello").exec_err()?;
+    ///     match never {}
+    /// }
+    /// ```
+    pub fn exec_err(&mut self) -> Result<Infallible, Error> {
+        Err(self.exec())
+    }
+
+    /// Like `exec`, but sort the error into one of the common reasons an
+    /// exec fails, so that it can be matched on without looking at OS error
+    /// codes.  If this function succeeds, it will never return.
+    ///
+    /// ```no_run
+    /// use exec::ExecFailure;
+    ///
+    /// match exec::Command::new("my-tool").exec_classified() {
+    ///     ExecFailure::NotFound => eprintln!("my-tool isn't installed"),
+    ///     ExecFailure::PermissionDenied => eprintln!("my-tool isn't executable"),
+    ///     failure => eprintln!("couldn't run my-tool: {}", failure),
+    /// }
+    /// ```
+    pub fn exec_classified(&mut self) -> ExecFailure {
+        self.exec().into()
+    }
+
+    /// Execute the command we built, making it replace the current process
+    /// as closely as the platform allows.  If this function succeeds, it
+    /// will never return.
+    ///
+    /// On Unix, this is exactly the same as `exec`.
+    ///
+    /// Windows has no real `exec`: the C runtime's version starts the new
+    /// program and then exits the current process immediately, so our
+    /// parent sees us finish while the program is still running.  That
+    /// breaks shells and other wrappers waiting on us.  So on Windows, this
+    /// instead runs the program as a child process, waits for it to finish,
+    /// and then exits the current process with the child's exit code.
+    /// `arg0` is ignored there, since Windows programs don't have an
+    /// `argv[0]` separate from their command line.
+    ///
+    /// ```no_run
+    /// let err = exec::Command::new("echo")
+    ///     .arg("hello")
+    ///     .exec_replace();
+    /// println!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn exec_replace(&mut self) -> Error {
+        self.exec()
+    }
+
+    /// Execute the command we built, making it replace the current process
+    /// as closely as the platform allows.  If this function succeeds, it
+    /// will never return.
+    ///
+    /// See the Unix version of this function for details.
+    #[cfg(windows)]
+    pub fn exec_replace(&mut self) -> Error {
+        match self.exec_with_status(|_| {}) {
+            Ok(never) => match never {},
+            Err(err) => err,
+        }
+    }
+
+    /// Like `exec_replace`, but returns a `Result` and lets `on_exit`
+    /// observe the program's exit status before the current process exits
+    /// with it.
+    ///
+    /// The two platforms behave quite differently here.  On Unix, the
+    /// current process is replaced by the program, so there's nothing left
+    /// to report the status to: `on_exit` is never called, and this
+    /// returns only if the exec fails.  On Windows, the program runs as a
+    /// child process, `on_exit` is called with its raw `DWORD` exit code
+    /// once it finishes, and then the current process exits with that
+    /// code.  Either way, an `Ok` value is never returned.
+    ///
+    /// ```no_run
+    /// let res = exec::Command::new("echo")
+    ///     .arg("hello")
+    ///     .exec_with_status(|code| eprintln!("echo exited with {}", code));
+    /// if let Err(err) = res {
+    ///     println!("Error: {}", err);
+    /// }
+    /// ```
+    #[cfg(unix)]
+    pub fn exec_with_status<F: FnOnce(u32)>(&mut self, _on_exit: F) -> Result<Infallible, Error> {
+        Err(self.exec())
+    }
+
+    /// Like `exec_replace`, but returns a `Result` and lets `on_exit`
+    /// observe the program's exit status before the current process exits
+    /// with it.
+    ///
+    /// See the Unix version of this function for details.
+    #[cfg(windows)]
+    pub fn exec_with_status<F: FnOnce(u32)>(&mut self, on_exit: F) -> Result<Infallible, Error> {
+        let status = self.spawn_child()?.wait().map_err(from_io_error)?;
+        // Windows always has an exit code, even if the child was killed.
+        let code = status.code().unwrap_or(1) as u32;
+        on_exit(code);
+        process::exit(code as i32)
+    }
+
+    /// Like `exec_replace`, but give up on the program if it's still
+    /// running after `timeout`.
+    ///
+    /// On Unix, the program replaces the current process right away, so
+    /// there's nothing left to enforce a timeout, and this is exactly the
+    /// same as `exec`.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let err = exec::Command::new("true").exec_timeout(Duration::from_secs(60));
+    /// panic!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn exec_timeout(&mut self, _timeout: Duration) -> Error {
+        self.exec()
+    }
+
+    /// Like `exec_replace`, but give up on the program if it's still
+    /// running after `timeout`.
+    ///
+    /// On Windows, we wait for the child process with
+    /// `WaitForSingleObject`.  If it's still running when `timeout` runs
+    /// out, it's terminated, and this returns `Error::TimedOut` instead of
+    /// exiting, so the caller can decide what to do next.  Timeouts too
+    /// long to express in milliseconds wait forever.
+    #[cfg(windows)]
+    pub fn exec_timeout(&mut self, timeout: Duration) -> Error {
+        use std::os::windows::io::AsRawHandle;
+
+        const WAIT_OBJECT_0: u32 = 0;
+        const WAIT_TIMEOUT: u32 = 0x102;
+        extern "system" {
+            fn WaitForSingleObject(handle: std::os::windows::io::RawHandle, millis: u32) -> u32;
+        }
+
+        let mut child = match self.spawn_child() {
+            Ok(child) => child,
+            Err(err) => return err,
+        };
+        // `u32::MAX` is `INFINITE`.
+        let millis = u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX);
+        match unsafe { WaitForSingleObject(child.as_raw_handle(), millis) } {
+            WAIT_OBJECT_0 => {}
+            WAIT_TIMEOUT => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Error::TimedOut;
+            }
+            _ => return from_io_error(io::Error::last_os_error()),
+        }
+        match child.wait() {
+            Ok(status) => process::exit(status.code().unwrap_or(1)),
+            Err(err) => from_io_error(err),
+        }
+    }
+
+    /// Apply our settings to the current process, and then start the
+    /// program as a child process, for the Windows emulation of `exec`.
+    #[cfg(windows)]
+    fn spawn_child(&self) -> Result<process::Child, Error> {
+        self.mark_used();
+        self.check_env()?;
+        self.validate()?;
+        self.setup()?;
+        let (program, search_path) = self.program()?;
+        self.call_on_exec(&program, search_path);
+        self.spawn_command(&program).spawn().map_err(from_io_error)
+    }
+
+    /// Make `exec_or_spawn` fall back to spawning the program as a child
+    /// process if the exec fails with one of the OS error codes in
+    /// `errnos`, such as `libc::ENOENT` or `libc::EACCES`.  Codes from
+    /// earlier calls are kept.  This can be chained.
+    pub fn spawn_fallback<I: IntoIterator<Item = i32>>(&mut self, errnos: I) -> &mut Command {
+        self.spawn_fallback.extend(errnos);
+        self
+    }
+
+    /// Execute the command we built, or, if that fails with one of the
+    /// errors passed to `spawn_fallback`, spawn it as a child process
+    /// instead and return its handle.  If the exec succeeds, this
+    /// function will never return.  No errors fall back by default, so
+    /// without `spawn_fallback` this always returns the exec error.
+    ///
+    /// The other process settings are applied to the current process
+    /// before the exec is attempted, and aren't undone if it fails, so the
+    /// child inherits them.  Only the program, its arguments and its
+    /// environment are passed to the child explicitly.  An error from
+    /// applying the settings, not just from the exec itself, can trigger
+    /// the fallback.
+    ///
+    /// ```no_run
+    /// let res = exec::Command::new("some-tool")
+    ///     .spawn_fallback(vec![libc::ENOENT, libc::EACCES])
+    ///     .exec_or_spawn();
+    /// match res {
+    ///     Ok(mut child) => println!("spawned instead: {:?}", child.wait()),
+    ///     Err(err) => println!("Error: {}", err),
+    /// }
+    /// ```
+    pub fn exec_or_spawn(&mut self) -> Result<process::Child, Error> {
+        let err = self.exec();
+        match err.raw_os_error() {
+            Some(code) if self.spawn_fallback.contains(&code) => {
+                let (program, _) = self.program()?;
+                self.spawn_command(&program).spawn().map_err(from_io_error)
+            }
+            _ => Err(err),
+        }
+    }
+
+    /// Run the command we built as a child process using `posix_spawn`,
+    /// wait for it to finish, and then exit the current process with its
+    /// exit status, as if it had replaced us.  If the program exits
+    /// because of a signal, we exit with 128 plus the signal number, like a
+    /// shell.  If this function succeeds, it will never return.
+    ///
+    /// This is only available on Unix.  It's an alternative to `exec` for
+    /// when the program must run in a separate process, such as when
+    /// something needs to outlive it in ours.  Some settings become spawn
+    /// attributes or file actions, which apply only to the child:
+    ///
+    /// - `stdin`, `stdout` and `stderr` become file actions that duplicate
+    ///   the descriptors onto the child's streams.  Files are opened by us
+    ///   first, before `chroot` or `current_dir`, just as they are by
+    ///   `exec`.
+    /// - `setsid` and `detach` use `POSIX_SPAWN_SETSID` where the platform
+    ///   has it, and otherwise fail with `ENOTSUP`.  `detach` doesn't need
+    ///   `TIOCNOTTY` here, since the child starts a new session anyway.
+    /// - `process_group` uses `POSIX_SPAWN_SETPGROUP`.
+    /// - `controlling_tty` has no equivalent, and fails with `ENOTSUP`.
+    /// - `reset_signals`, `restore_sigpipe` and `reset_signal` use
+    ///   `POSIX_SPAWN_SETSIGDEF`, and `reset_signals` also uses
+    ///   `POSIX_SPAWN_SETSIGMASK`.
+    ///
+    /// Everything else, including `current_dir`, `chroot`, `umask`,
+    /// `rlimit`, `nice`, the user and group IDs and `pre_exec` closures,
+    /// applies to the current process before spawning, and the child
+    /// inherits it.  `close_fds_from` and `keep_fds` mark descriptors
+    /// close-on-exec instead of closing them.  `shell_fallback` is ignored.
+    ///
+    /// ```
+    /// // We exit with whatever status the shell does, so this check only
+    /// // passes if the shell sees its output redirected.
+    /// let err = exec::Command::new("sh")
+    ///     .args(&["-c", "test -c /dev/stdout && ! test -t 1"])
+    ///     .stdout(exec::Stdio::Path("/dev/null".into()))
+    ///     .spawn_replace();
+    /// panic!("Error: {}", err);
+    /// ```
+    #[cfg(unix)]
+    pub fn spawn_replace(&mut self) -> Error {
+        match self.spawn_and_wait() {
+            Ok(code) => process::exit(code),
+            Err(err) => err,
+        }
+    }
+
+    /// Spawn the program for `spawn_replace`, and return the status we
+    /// should exit with once it's done.
+    #[cfg(unix)]
+    fn spawn_and_wait(&self) -> Result<i32, Error> {
+        self.mark_used();
+        self.check_env()?;
+        let search_late = self.searches_after_setup();
+        let early = if search_late {
+            self.validate()?;
+            None
+        } else {
+            Some(self.program()?)
+        };
+        let argv_os = self.exec_argv();
+        let argv = CStringArray::from_args(&argv_os)?;
+        let envp = match self.build_env() {
+            Some(env) => CStringArray::from_env(env)?,
+            None => CStringArray::from_env(env::vars_os())?,
+        };
+        let actions = SpawnFileActions::new(&self.stdio)?;
+        let attr = SpawnAttr::new(self)?;
+        self.setup_for(true, None)?;
+        let (program, search_path) = match early {
+            Some(early) => early,
+            None => self.program()?,
+        };
+        let program_cstring = to_cstring(&program)?;
+        self.call_on_exec(&program, search_path);
+        self.write_audit_record(&argv_os)?;
+
+        let mut pid = 0;
+        let res = unsafe {
+            if search_path {
+                libc::posix_spawnp(
+                    &mut pid,
+                    program_cstring.as_ptr(),
+                    &actions.0,
+                    &attr.0,
+                    argv.as_ptr() as *const *mut libc::c_char,
+                    envp.as_ptr() as *const *mut libc::c_char,
+                )
+            } else {
+                libc::posix_spawn(
+                    &mut pid,
+                    program_cstring.as_ptr(),
+                    &actions.0,
+                    &attr.0,
+                    argv.as_ptr() as *const *mut libc::c_char,
+                    envp.as_ptr() as *const *mut libc::c_char,
+                )
+            }
+        };
+        check_spawn(res)?;
+        // Only the child needs the files we opened, so don't hold them open
+        // while it runs.
+        drop(actions);
+
+        let mut status = 0;
+        cvt_r(|| unsafe { libc::waitpid(pid, &mut status, 0) })?;
+        if libc::WIFSIGNALED(status) {
+            Ok(128 + libc::WTERMSIG(status))
+        } else {
+            Ok(libc::WEXITSTATUS(status))
+        }
+    }
+
+    /// Does the program have to be found using `with_path`?  That search
+    /// must see the root and current directory the program will run in, so
+    /// it happens after `setup`, once everything has been checked.
+    fn searches_after_setup(&self) -> bool {
+        self.program_path.is_none() && self.search_path.is_some()
+    }
+
+    /// The program to run, and whether `PATH` should be searched for it.
+    fn program(&self) -> Result<(OsString, bool), Error> {
+        self.check_absolute_path()?;
+        match (&self.program_path, &self.search_path) {
+            (Some(path), _) => Ok((path.clone(), false)),
+            // Searching would look at the filesystem, which Miri's isolation
+            // rejects, and the program won't be run under it anyway.
+            (None, Some(_)) if cfg!(miri) => Ok((self.argv[0].clone(), false)),
+            (None, Some(dirs)) => match resolve_in(&self.argv[0], Some(dirs)) {
+                Some(path) => Ok((path.into_os_string(), false)),
+                None => Err(Error::Errno(Errno(libc::ENOENT))),
+            },
+            (None, None) => Ok((self.argv[0].clone(), true)),
+        }
+    }
+
+    /// A `std::process::Command` that runs `program`, as returned by
+    /// `program`, with the same arguments and environment, for when we
+    /// can't exec it.
+    fn spawn_command(&self, program: &OsStr) -> process::Command {
+        let mut cmd = process::Command::new(program);
+        let argv = self.exec_argv();
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+
+            cmd.args(&argv[1..]);
+            cmd.arg0(&argv[0]);
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+
+            for (i, arg) in argv.iter().enumerate().skip(1) {
+                if self.raw_args.contains(&i) {
+                    cmd.raw_arg(arg);
+                } else {
+                    cmd.arg(arg);
+                }
+            }
+        }
+        if let Some(env) = self.build_env() {
+            cmd.env_clear().envs(env);
+        }
+        cmd
+    }
+
+    /// Change the state of the current process as requested, right before
+    /// it's replaced by the new program.
+    fn setup(&self) -> Result<(), Error> {
+        self.setup_for(false, None)
+    }
+
+    /// Like `setup`, but if `spawn` is true, leave out the changes that
+    /// `spawn_replace` makes in the child instead.  The order here is
+    /// documented on `exec`, and security-sensitive combinations rely on
+    /// it.  Descriptors that would be closed are just marked close-on-exec,
+    /// since the child may still need to duplicate them onto its standard
+    /// streams.  On Unix, `program_fd` is a descriptor we still need to run
+    /// the program from, which `close_fds_from` must leave open.
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    fn setup_for(&self, spawn: bool, program_fd: Option<i32>) -> Result<(), Error> {
+        #[cfg(unix)]
+        let chroot = match self.chroot {
+            Some(ref dir) => Some(to_cstring(dir.as_os_str())?),
+            None => None,
+        };
+        #[cfg(unix)]
+        {
+            if !spawn {
+                self.redirect_stdio()?;
+            }
+            if let Some(lowest) = self.close_fds_from {
+                if spawn {
+                    cloexec_fds_from(lowest, &[])?;
+                } else {
+                    let mut keep = self.keep_fds.clone().unwrap_or_default();
+                    keep.extend(program_fd);
+                    close_fds_from(lowest, &keep)?;
+                }
+            }
+            if let Some(ref keep) = self.keep_fds {
+                cloexec_fds_from(0, keep)?;
+            }
+            if !spawn {
+                if self.detach {
+                    drop_controlling_tty()?;
+                }
+                if self.setsid {
+                    cvt(unsafe { libc::setsid() })?;
+                }
+                if let Some(fd) = self.controlling_tty {
+                    cvt_r(|| unsafe { libc::ioctl(fd, libc::TIOCSCTTY as _, 0) })?;
+                }
+                if let Some(pgid) = self.process_group {
+                    cvt(unsafe { libc::setpgid(0, pgid) })?;
+                }
+            }
+            if let Some(ref dir) = chroot {
+                cvt_r(|| unsafe { libc::chroot(dir.as_ptr()) })?;
+                cvt_r(|| unsafe { libc::chdir(b"/\0".as_ptr() as *const libc::c_char) })?;
+            }
+        }
+        if let Some(ref dir) = self.current_dir {
+            loop {
+                match env::set_current_dir(dir) {
+                    Ok(()) => break,
+                    Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                    Err(err) => return Err(from_io_error(err)),
+                }
+            }
+        }
+        #[cfg(unix)]
+        {
+            if let Some(mask) = self.umask {
+                unsafe { libc::umask(mask as libc::mode_t) };
+            }
+            for &(resource, soft, hard) in &self.rlimits {
+                let limit = libc::rlimit {
+                    rlim_cur: soft as libc::rlim_t,
+                    rlim_max: hard as libc::rlim_t,
+                };
+                cvt_r(|| unsafe { libc::setrlimit(resource as _, &limit) })?;
+            }
+            if let Some(adjustment) = self.nice {
+                // `nice` returns the new niceness, which may legitimately
+                // be -1, so errors can only be told apart using `errno`.
+                errno::set_errno(Errno(0));
+                if unsafe { libc::nice(adjustment) } == -1 && errno().0 != 0 {
+                    return Err(Error::Errno(errno()));
+                }
+            }
+            if spawn {
+                // Handled by the spawn attributes.
+            } else if self.reset_signals {
+                reset_signals()?;
+            } else {
+                if self.restore_sigpipe {
+                    unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL) };
+                }
+                for &sig in &self.default_signals {
+                    if unsafe { libc::signal(sig, libc::SIG_DFL) } == libc::SIG_ERR {
+                        return Err(Error::Errno(errno()));
+                    }
+                }
+            }
+            if let Some(ref groups) = self.groups {
+                cvt(unsafe { libc::setgroups(groups.len() as _, groups.as_ptr()) })?;
+            }
+            if let Some(gid) = self.gid {
+                cvt(unsafe { libc::setgid(gid) })?;
+            }
+            if let Some(uid) = self.uid {
+                cvt(unsafe { libc::setuid(uid) })?;
+            }
+        }
+        for f in &self.pre_exec {
+            // A panic in a previous `exec` attempt poisons the lock, but
+            // that doesn't make the closure itself unusable.
+            let mut f = f.lock().unwrap_or_else(|err| err.into_inner());
+            (*f)().map_err(Error::PreExec)?;
+        }
+        Ok(())
+    }
+
+    /// Call the closure registered with `on_exec`, if there is one and it
+    /// hasn't been called yet.
+    fn call_on_exec(&self, program: &OsStr, search_path: bool) {
+        let f = match self.on_exec {
+            Some(ref f) => f.lock().unwrap_or_else(|err| err.into_inner()).take(),
+            None => None,
+        };
+        if let Some(f) = f {
+            let path = if search_path {
+                resolve_in_path(program).map(PathBuf::into_os_string)
+            } else {
+                None
+            };
+            let argv: Vec<OsString> = self.exec_argv().into_iter().map(Cow::into_owned).collect();
+            f(path.as_deref().unwrap_or(program), &argv);
+        }
+    }
+
+    /// Write `argv` to `audit_fd`, if it's set.
+    #[cfg(unix)]
+    fn write_audit_record(&self, argv: &[Cow<OsStr>]) -> Result<(), Error> {
+        use std::os::unix::ffi::OsStrExt;
+
+        if let Some(fd) = self.audit_fd {
+            let mut record = vec![];
+            for arg in argv {
+                record.extend_from_slice(arg.as_bytes());
+                record.push(0);
+            }
+            record.push(b'\n');
+            write_all_fd(fd, &record)?;
+            // The record is all the descriptor is for, so don't leak it
+            // unless we were asked to.
+            let kept = self
+                .keep_fds
+                .as_ref()
+                .is_some_and(|keep| keep.contains(&fd));
+            if fd > 2 && !kept {
+                set_cloexec(fd, true)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Connect standard input, output and error as requested.
+    #[cfg(unix)]
+    fn redirect_stdio(&self) -> Result<(), Error> {
+        let mut to_close = vec![];
+        for (target, stdio) in self.stdio.iter().enumerate() {
+            let target = target as RawFd;
+            if let Some((fd, close)) = stdio.open(target)? {
+                if fd != target {
+                    cvt_r(|| unsafe { libc::dup2(fd, target) })?;
+                }
+                if close && !to_close.contains(&fd) {
+                    to_close.push(fd);
+                }
+            }
+        }
+        // Wait until everything is connected, since the same descriptor
+        // may be used for more than one stream.
+        for fd in to_close {
+            close_fd(fd)?;
+        }
+        Ok(())
+    }
+
+    /// The `argv` array the new program will see.  The arguments are
+    /// borrowed from the builder, unless `expand_env` changed them.
+    fn exec_argv(&self) -> Vec<Cow<'_, OsStr>> {
+        let arg0 = Cow::Borrowed(self.arg0.as_ref().unwrap_or(&self.argv[0]).as_os_str());
+        if !self.expand_env {
+            return iter::once(arg0)
+                .chain(
+                    self.argv[1..]
+                        .iter()
+                        .map(|arg| Cow::Borrowed(arg.as_os_str())),
+                )
+                .collect();
+        }
+        let env = self.build_env();
+        let lookup = |name: &OsStr| match env {
+            Some(ref env) => env
+                .iter()
+                .find(|(key, _)| same_env_key(key, name))
+                .map(|(_, val)| val.clone()),
+            None => env::var_os(name),
+        };
+        iter::once(arg0)
+            .chain(
+                self.argv[1..]
+                    .iter()
+                    .map(|arg| Cow::Owned(expand_vars(arg, lookup))),
+            )
+            .collect()
+    }
+
+    /// The `argv` array the new program will see, with each argument quoted
+    /// for the C runtime unless it was added with `raw_arg`.
+    #[cfg(windows)]
+    fn quoted_argv(&self) -> Vec<OsString> {
+        self.exec_argv()
+            .into_iter()
+            .enumerate()
+            .map(|(i, arg)| {
+                if self.raw_args.contains(&i) {
+                    arg.into_owned()
+                } else {
+                    quote_arg(&arg)
+                }
+            })
+            .collect()
+    }
+
+    /// Set or, if `val` is `None`, remove a variable in `env_vars`, and
+    /// note whether that contradicts an earlier call.
+    fn set_env_var(&mut self, key: OsString, val: Option<OsString>) {
+        match self.env_vars.entry(key) {
+            Entry::Occupied(mut entry) => {
+                if *entry.get() != val && !self.env_conflicts.contains(entry.key()) {
+                    self.env_conflicts.push(entry.key().clone());
+                }
+                entry.insert(val);
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(val);
+            }
+        }
+    }
+
+    /// Note that this command has been run or checked, so that the
+    /// `warn-unexecuted` feature doesn't warn about it.
+    fn mark_used(&self) {
+        #[cfg(all(feature = "warn-unexecuted", debug_assertions))]
+        self.used
+            .0
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Fail if `require_absolute_path` is on and the program isn't given by
+    /// an absolute path.
+    fn check_absolute_path(&self) -> Result<(), Error> {
+        let program = self.program_path.as_ref().unwrap_or(&self.argv[0]);
+        if self.require_absolute_path && !Path::new(program).is_absolute() {
+            return Err(Error::RelativePath(program.clone()));
+        }
+        Ok(())
+    }
+
+    /// Fail if `strict_env` is on and the environment settings conflict.
+    fn check_env(&self) -> Result<(), Error> {
+        match self.env_conflicts.first() {
+            Some(key) if self.strict_env => Err(Error::EnvConflict(key.clone())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Compute the environment for the new program, or `None` if it should
+    /// simply inherit ours.
+    fn build_env(&self) -> Option<Vec<(OsString, OsString)>> {
+        if !self.env_clear
+            && self.env_base.is_none()
+            && self.env_retain.is_empty()
+            && self.env_vars.is_empty()
+        {
+            return None;
+        }
+        let mut base = match self.env_base {
+            Some(ref base) => base.clone(),
+            None if self.env_clear && self.env_inherit.is_empty() => vec![],
+            None => env::vars_os()
+                .filter(|(key, _)| {
+                    !self.env_clear || self.env_inherit.iter().any(|k| same_env_key(k, key))
+                })
+                .collect(),
+        };
+        for f in &self.env_retain {
+            let mut f = f.lock().unwrap_or_else(|err| err.into_inner());
+            base.retain(|(key, val)| f(key, val));
+        }
+        let mut env: Vec<_> = base
+            .into_iter()
+            .filter(|(key, _)| !self.env_vars.keys().any(|k| same_env_key(k, key)))
+            .collect();
+        env.extend(
+            self.env_vars
+                .iter()
+                .filter_map(|(key, val)| val.as_ref().map(|val| (key.clone(), val.clone()))),
+        );
+        Some(env)
+    }
+}
+
+/// Show the command line along with any other settings that have been
+/// changed from their defaults.
+///
+/// ```
+/// let mut cmd = exec::Command::new("echo");
+/// cmd.arg("hello world");
+/// assert_eq!(format!("{:?}", cmd), r#"Command { argv: ["echo", "hello world"] }"#);
+/// ```
+impl fmt::Debug for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut dbg = f.debug_struct("Command");
+        dbg.field("argv", &self.argv);
+        if let Some(ref arg0) = self.arg0 {
+            dbg.field("arg0", arg0);
+        }
+        #[cfg(windows)]
+        {
+            if !self.raw_args.is_empty() {
+                dbg.field("raw_args", &self.raw_args);
+            }
+        }
+        if let Some(ref path) = self.program_path {
+            dbg.field("program_path", path);
+        }
+        if let Some(ref path) = self.search_path {
+            dbg.field("search_path", path);
+        }
+        if self.require_absolute_path {
+            dbg.field("require_absolute_path", &self.require_absolute_path);
+        }
+        if self.env_clear {
+            dbg.field("env_clear", &self.env_clear);
+        }
+        if !self.env_inherit.is_empty() {
+            dbg.field("env_inherit", &self.env_inherit);
+        }
+        if let Some(ref base) = self.env_base {
+            dbg.field("env_base", base);
+        }
+        if self.expand_env {
+            dbg.field("expand_env", &self.expand_env);
+        }
+        if !self.spawn_fallback.is_empty() {
+            dbg.field("spawn_fallback", &self.spawn_fallback);
+        }
+        if self.strict_env {
+            dbg.field("strict_env", &self.strict_env);
+        }
+        if !self.env_vars.is_empty() {
+            dbg.field("env", &self.env_vars);
+        }
+        if let Some(ref dir) = self.current_dir {
+            dbg.field("current_dir", dir);
+        }
+        #[cfg(unix)]
+        {
+            if let Some(ref dir) = self.chroot {
+                dbg.field("chroot", dir);
+            }
+            if let Some(ref uid) = self.uid {
+                dbg.field("uid", uid);
+            }
+            if let Some(ref gid) = self.gid {
+                dbg.field("gid", gid);
+            }
+            if let Some(ref groups) = self.groups {
+                dbg.field("groups", groups);
+            }
+            for (name, stdio) in ["stdin", "stdout", "stderr"].iter().zip(&self.stdio) {
+                if *stdio != Stdio::Inherit {
+                    dbg.field(name, stdio);
+                }
+            }
+            if let Some(ref lowest) = self.close_fds_from {
+                dbg.field("close_fds_from", lowest);
+            }
+            if let Some(ref keep) = self.keep_fds {
+                dbg.field("keep_fds", keep);
+            }
+            if let Some(ref fd) = self.audit_fd {
+                dbg.field("audit_fd", fd);
+            }
+            if self.setsid {
+                dbg.field("setsid", &self.setsid);
+            }
+            if self.detach {
+                dbg.field("detach", &self.detach);
+            }
+            if let Some(ref fd) = self.controlling_tty {
+                dbg.field("controlling_tty", fd);
+            }
+            if let Some(ref pgid) = self.process_group {
+                dbg.field("process_group", pgid);
+            }
+            if let Some(ref mask) = self.umask {
+                dbg.field("umask", &format_args!("{:#o}", mask));
+            }
+            if !self.rlimits.is_empty() {
+                dbg.field("rlimits", &self.rlimits);
+            }
+            if let Some(ref adjustment) = self.nice {
+                dbg.field("nice", adjustment);
+            }
+            if self.reset_signals {
+                dbg.field("reset_signals", &self.reset_signals);
+            }
+            if self.restore_sigpipe {
+                dbg.field("restore_sigpipe", &self.restore_sigpipe);
+            }
+            if !self.default_signals.is_empty() {
+                dbg.field("default_signals", &self.default_signals);
+            }
+            if self.shell_fallback {
+                dbg.field("shell_fallback", &self.shell_fallback);
+            }
+        }
+        if !self.env_retain.is_empty() {
+            dbg.field(
+                "env_retain",
+                &format_args!("<{} closures>", self.env_retain.len()),
+            );
+        }
+        if !self.pre_exec.is_empty() {
+            dbg.field(
+                "pre_exec",
+                &format_args!("<{} closures>", self.pre_exec.len()),
+            );
+        }
+        if self.on_exec.is_some() {
+            dbg.field("on_exec", &format_args!("<closure>"));
+        }
+        dbg.finish()
+    }
+}
+
+/// The error from converting an empty `argv` into a `Command`, which needs
+/// at least the name of the program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EmptyArgv;
+
+impl fmt::Display for EmptyArgv {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "argv is empty, so there's no program to run")
+    }
+}
+
+impl error::Error for EmptyArgv {}
+
+/// Build a command from a complete `argv`, like `Command::from_argv`.
+///
+/// ```
+/// use std::convert::TryFrom;
+/// use std::ffi::OsString;
+///
+/// let cmd = exec::Command::try_from(vec![OsString::from("echo"), OsString::from("hello")]).unwrap();
+/// assert_eq!(cmd.get_program(), "echo");
+/// assert_eq!(cmd.get_args().collect::<Vec<_>>(), &["hello"]);
+/// assert_eq!(exec::Command::try_from(vec![]).unwrap_err(), exec::EmptyArgv);
+/// ```
+impl TryFrom<Vec<OsString>> for Command {
+    type Error = EmptyArgv;
+
+    fn try_from(argv: Vec<OsString>) -> Result<Command, EmptyArgv> {
+        Command::from_argv(argv).ok_or(EmptyArgv)
+    }
+}
+
+/// The part of a `Command` that `serde` can save and restore.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CommandData {
+    program: OsString,
+    args: Vec<OsString>,
+    #[serde(default)]
+    arg0: Option<OsString>,
+    #[serde(default)]
+    env_clear: bool,
+    /// A list of pairs rather than a map, since some formats only allow
+    /// strings as keys.
+    #[serde(default)]
+    env: Vec<(OsString, Option<OsString>)>,
+    #[serde(default)]
+    current_dir: Option<PathBuf>,
+}
+
+/// With the `serde` feature, a `Command` can be saved and rebuilt later,
+/// as its program, arguments, `arg0`, `current_dir`, and the environment
+/// variables set or removed with `env`, `env_remove` and `env_clear`.
+/// Everything else, including closures like `pre_exec` and the other ways
+/// of choosing an environment, such as `env_from`, is left out, so the
+/// rebuilt command has those settings at their defaults.  On Windows,
+/// arguments added with `raw_arg` come back as ordinary arguments.
+///
+/// Strings are stored using `serde`'s own format for `OsString`, which is
+/// the raw bytes on Unix and the UTF-16 code units on Windows, so nothing
+/// is lost even if they aren't valid Unicode, but a command saved on one
+/// kind of system can't be loaded on the other.
+///
+/// ```
+/// let mut cmd = exec::Command::with_args("ls", &["-l"]);
+/// cmd.env("LANG", "C").current_dir("/tmp");
+/// let json = serde_json::to_string(&cmd).unwrap();
+/// let loaded: exec::Command = serde_json::from_str(&json).unwrap();
+/// assert_eq!(loaded.get_program(), "ls");
+/// assert_eq!(loaded.get_args().collect::<Vec<_>>(), ["-l"]);
+/// assert_eq!(format!("{:?}", loaded), format!("{:?}", cmd));
+///
+/// #[cfg(unix)]
+/// {
+///     use std::ffi::OsStr;
+///     use std::os::unix::ffi::OsStrExt;
+///
+///     let arg = OsStr::from_bytes(b"caf\xe9");
+///     let json = serde_json::to_string(&exec::Command::with_args("echo", &[arg])).unwrap();
+///     let loaded: exec::Command = serde_json::from_str(&json).unwrap();
+///     assert_eq!(loaded.get_args().next(), Some(arg));
+/// }
+/// ```
+#[cfg(feature = "serde")]
+impl serde::Serialize for Command {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        CommandData {
+            program: self.argv[0].clone(),
+            args: self.argv[1..].to_vec(),
+            arg0: self.arg0.clone(),
+            env_clear: self.env_clear,
+            env: self
+                .env_vars
+                .iter()
+                .map(|(key, val)| (key.clone(), val.clone()))
+                .collect(),
+            current_dir: self.current_dir.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Command {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Command, D::Error> {
+        let data = CommandData::deserialize(deserializer)?;
+        let mut cmd = Command::with_args(data.program, data.args);
+        if let Some(arg0) = data.arg0 {
+            cmd.arg0(arg0);
+        }
+        if data.env_clear {
+            cmd.env_clear();
+        }
+        for (key, val) in data.env {
+            match val {
+                Some(val) => cmd.env(key, val),
+                None => cmd.env_remove(key),
+            };
+        }
+        if let Some(dir) = data.current_dir {
+            cmd.current_dir(dir);
+        }
+        Ok(cmd)
+    }
+}
+
+/// Extensions to `std::process::Command` for running it with `exec`.
+///
+/// This is only available on Unix.
+#[cfg(unix)]
+pub trait CommandExt {
+    /// Replace the current process with the program this command would
+    /// run.  If this function succeeds, it will never return.
+    ///
+    /// The standard library doesn't let us read back everything about a
+    /// `std::process::Command`, so only its program, arguments, explicit
+    /// environment changes and working directory are used.  In particular,
+    /// `env_clear`, stdio settings, and anything set through
+    /// `std::os::unix::process::CommandExt` are ignored.  If you need them,
+    /// build one of our `Command`s instead.
+    ///
+    /// Since the standard library has its own Unix `CommandExt` trait with
+    /// an `exec` method, you may need to say which one you mean.
+    ///
+    /// ```
+    /// use exec::CommandExt;
+    ///
+    /// // The shell exits successfully only if it sees our settings.
+    /// let mut cmd = std::process::Command::new("sh");
+    /// cmd.args(&["-c", "test \"$LANG\" = C && test \"$(pwd)\" = /"])
+    ///     .env("LANG", "C")
+    ///     .current_dir("/");
+    /// let err = CommandExt::exec(&mut cmd);
+    /// panic!("Error: {}", err);
+    /// ```
+    fn exec(&mut self) -> Error;
+}
+
+#[cfg(unix)]
+impl CommandExt for process::Command {
+    fn exec(&mut self) -> Error {
+        let mut cmd = Command::new(self.get_program());
+        cmd.args(self.get_args());
+        for (key, val) in self.get_envs() {
+            match val {
+                Some(val) => cmd.env(key, val),
+                None => cmd.env_remove(key),
+            };
+        }
+        if let Some(dir) = self.get_current_dir() {
+            cmd.current_dir(dir);
+        }
+        cmd.exec()
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::ffi::CString;
+
+    /// A `Syscalls` that returns `res` with `errno` set to `code`, and
+    /// remembers how it was called.
+    struct Mock {
+        res: libc::c_int,
+        code: i32,
+        called: Cell<Option<(CString, usize, bool, bool)>>,
+    }
+
+    impl Mock {
+        fn new(res: libc::c_int, code: i32) -> Mock {
+            Mock {
+                res,
+                code,
+                called: Cell::new(None),
+            }
+        }
+    }
+
+    impl Syscalls for Mock {
+        unsafe fn exec(
+            &self,
+            program: &std::ffi::CStr,
+            argv: *const *const libc::c_char,
+            envp: Option<*const *const libc::c_char>,
+            search_path: bool,
+        ) -> libc::c_int {
+            let mut argc = 0;
+            while !(*argv.add(argc)).is_null() {
+                argc += 1;
+            }
+            self.called.set(Some((
+                program.to_owned(),
+                argc,
+                envp.is_some(),
+                search_path,
+            )));
+            self.res
+        }
+
+        fn errno(&self) -> Errno {
+            Errno(self.code)
+        }
+    }
+
+    fn run(sys: &Mock, envp: Option<&CStringArray>, search_path: bool) -> Error {
+        let program = CString::new("prog").unwrap();
+        let argv = CStringArray::from_args(&["prog", "arg"]).unwrap();
+        sys_exec(
+            sys,
+            &program,
+            argv.as_slice(),
+            envp.map(CStringArray::as_slice),
+            search_path,
+        )
+    }
+
+    #[test]
+    fn failure_reports_errno() {
+        let sys = Mock::new(-1, libc::ENOENT);
+        assert_eq!(run(&sys, None, true), Error::Errno(Errno(libc::ENOENT)));
+    }
+
+    #[test]
+    fn return_without_failure_is_unexpected() {
+        let sys = Mock::new(0, libc::ENOENT);
+        assert_eq!(run(&sys, None, false), Error::Unexpected);
+    }
+
+    #[test]
+    fn arguments_are_passed_through() {
+        let envp = CStringArray::from_env(vec![("KEY", "value")]).unwrap();
+        let sys = Mock::new(-1, libc::EACCES);
+        assert_eq!(
+            run(&sys, Some(&envp), false),
+            Error::Errno(Errno(libc::EACCES))
+        );
+        assert_eq!(
+            sys.called.take(),
+            Some((CString::new("prog").unwrap(), 2, true, false))
+        );
+    }
+
+    #[test]
+    fn std_nul_errors_are_null_bytes() {
+        let nul = Error::NullByteInArgument {
+            index: None,
+            value: OsString::new(),
+        };
+        let err = std::fs::File::open("nul\0byte").unwrap_err();
+        assert_eq!(from_io_error(err), nul);
+        let err = process::Command::new("nul\0byte").spawn().unwrap_err();
+        assert_eq!(from_io_error(err), nul);
+    }
+
+    #[test]
+    fn other_errors_keep_their_message() {
+        let err = from_io_error(io::Error::new(io::ErrorKind::InvalidInput, "bad handle"));
+        assert_eq!(err.to_string(), "couldn't run process: bad handle");
+        assert_eq!(err.raw_os_error(), None);
+        let err = from_io_error(io::Error::other("something else"));
+        assert!(matches!(err, Error::Io(ref err) if err.kind() == io::ErrorKind::Other));
+    }
+
+    /// On Windows, `spawn_child` and `exec_timeout` get errors like this
+    /// one, which `std` returns without an OS error code when it refuses to
+    /// pass arguments to a batch file.
+    #[test]
+    fn spawn_failure_without_os_code_is_not_a_null_byte() {
+        let spawn_err = io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "batch file arguments are invalid",
+        );
+        let err = from_io_error(spawn_err);
+        assert!(matches!(err, Error::Io(_)), "{:?}", err);
+        assert_eq!(
+            err.to_string(),
+            "couldn't run process: batch file arguments are invalid"
+        );
+        let err = io::Error::from(err);
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(err.to_string(), "batch file arguments are invalid");
+    }
+}
@@ -0,0 +1,303 @@
+//! Tests of the success path.  A successful `exec` replaces the process
+//! running it, so each of these forks, execs in the child with its
+//! standard output connected to a pipe, and checks what the program wrote
+//! and how it exited.
+//!
+//! This doesn't use the standard test harness, which runs tests on several
+//! threads at once: after `fork` in a multithreaded program, only
+//! async-signal-safe functions may be called, and `exec` isn't one.
+//! Instead, `main` runs the tests one after another on its only thread.
+
+extern crate exec;
+#[cfg(unix)]
+extern crate libc;
+
+/// A test's name, the function that runs it, and why it's ignored by
+/// default, if it is.
+#[cfg(unix)]
+type Test = (&'static str, fn(), Option<&'static str>);
+
+#[cfg(unix)]
+fn main() {
+    // Tests that need root, to `chroot` and change user, are ignored unless
+    // asked for with `--ignored` or `--include-ignored`, as with the standard
+    // harness.
+    let tests: &[Test] = &[
+        (
+            "runs_program_with_arguments",
+            tests::runs_program_with_arguments,
+            None,
+        ),
+        ("searches_path", tests::searches_path, None),
+        ("passes_argv_exactly", tests::passes_argv_exactly, None),
+        ("sets_argv0", tests::sets_argv0, None),
+        ("passes_environment", tests::passes_environment, None),
+        (
+            "free_function_runs_program",
+            tests::free_function_runs_program,
+            None,
+        ),
+        ("reports_exit_status", tests::reports_exit_status, None),
+        (
+            "harness_sees_failed_exec",
+            tests::harness_sees_failed_exec,
+            None,
+        ),
+        (
+            "chdir_happens_inside_chroot",
+            tests::chdir_happens_inside_chroot,
+            Some("needs root"),
+        ),
+        (
+            "privileges_are_dropped_after_chroot",
+            tests::privileges_are_dropped_after_chroot,
+            Some("needs root"),
+        ),
+        (
+            "streams_are_connected_before_chroot",
+            tests::streams_are_connected_before_chroot,
+            Some("needs root"),
+        ),
+        (
+            "streams_are_connected_before_chdir",
+            tests::streams_are_connected_before_chdir,
+            None,
+        ),
+        (
+            "spawned_streams_are_connected_before_chdir",
+            tests::spawned_streams_are_connected_before_chdir,
+            None,
+        ),
+    ];
+    // Like the standard harness, take any argument that isn't an option
+    // as a filter on the test names.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let filter = args.iter().find(|arg| !arg.starts_with('-'));
+    let only_ignored = args.iter().any(|arg| arg == "--ignored");
+    let include_ignored = only_ignored || args.iter().any(|arg| arg == "--include-ignored");
+    let (mut passed, mut ignored) = (0, 0);
+    for &(name, test, ignore) in tests {
+        if filter.is_some_and(|filter| !name.contains(filter.as_str()))
+            || (only_ignored && ignore.is_none())
+        {
+            continue;
+        }
+        match ignore {
+            Some(reason) if !include_ignored => {
+                println!("test {} ... ignored, {}", name, reason);
+                ignored += 1;
+            }
+            _ => {
+                test();
+                println!("test {} ... ok", name);
+                passed += 1;
+            }
+        }
+    }
+    println!(
+        "\ntest result: ok. {} passed; 0 failed; {} ignored; 0 measured; {} filtered out",
+        passed,
+        ignored,
+        tests.len() - passed - ignored
+    );
+}
+
+#[cfg(not(unix))]
+fn main() {}
+
+#[cfg(unix)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+    use std::os::unix::io::FromRawFd;
+    use std::path::PathBuf;
+    use std::process;
+
+    /// Fork, and run `exec` in the child.  Returns everything the child wrote
+    /// to standard output, and its exit status.  If `exec` fails, the child
+    /// writes the error to standard error and exits with status 127.
+    fn run_in_child<F: FnOnce() -> exec::Error>(exec: F) -> (String, i32) {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0, "pipe failed");
+        let (read_fd, write_fd) = (fds[0], fds[1]);
+
+        // Anything left in our buffer would be written again by the child.
+        std::io::stdout().flush().unwrap();
+        let pid = unsafe { libc::fork() };
+        assert!(pid >= 0, "fork failed");
+        if pid == 0 {
+            unsafe {
+                libc::close(read_fd);
+                libc::dup2(write_fd, 1);
+                libc::close(write_fd);
+            }
+            let err = exec();
+            let _ = writeln!(std::io::stderr(), "Error: {}", err);
+            unsafe { libc::_exit(127) };
+        }
+
+        unsafe { libc::close(write_fd) };
+        let mut output = String::new();
+        unsafe { File::from_raw_fd(read_fd) }
+            .read_to_string(&mut output)
+            .unwrap();
+        let mut status = 0;
+        assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+        assert!(libc::WIFEXITED(status), "child didn't exit normally");
+        (output, libc::WEXITSTATUS(status))
+    }
+
+    pub fn runs_program_with_arguments() {
+        let res = run_in_child(|| {
+            exec::Command::new("/bin/echo")
+                .args(["hello", "world"])
+                .exec()
+        });
+        assert_eq!(res, ("hello world\n".to_owned(), 0));
+    }
+
+    pub fn searches_path() {
+        let res = run_in_child(|| exec::Command::new("echo").arg("found").exec());
+        assert_eq!(res, ("found\n".to_owned(), 0));
+    }
+
+    pub fn passes_argv_exactly() {
+        let res = run_in_child(|| {
+            exec::Command::new("/bin/sh")
+                .args([
+                    "-c",
+                    "printf '%s|' \"$0\" \"$@\"",
+                    "first",
+                    "with space",
+                    "",
+                ])
+                .exec()
+        });
+        assert_eq!(res, ("first|with space||".to_owned(), 0));
+    }
+
+    pub fn sets_argv0() {
+        let res = run_in_child(|| {
+            exec::Command::new("/bin/sh")
+                .arg0("custom-name")
+                .args(["-c", "printf %s \"$0\""])
+                .exec()
+        });
+        // With nothing after the script, `$0` is the `argv[0]` we passed.
+        assert_eq!(res, ("custom-name".to_owned(), 0));
+    }
+
+    pub fn passes_environment() {
+        let res = run_in_child(|| {
+            exec::Command::new("/bin/sh")
+                .args(["-c", "printf '%s,%s' \"$EXEC_TEST_VAR\" \"${HOME-unset}\""])
+                .env_clear()
+                .env("EXEC_TEST_VAR", "some value")
+                .exec()
+        });
+        assert_eq!(res, ("some value,unset".to_owned(), 0));
+    }
+
+    pub fn free_function_runs_program() {
+        let res = run_in_child(|| exec::execvp("echo", &["echo", "from", "execvp"]));
+        assert_eq!(res, ("from execvp\n".to_owned(), 0));
+    }
+
+    pub fn reports_exit_status() {
+        let res = run_in_child(|| exec::Command::new("/bin/sh").args(["-c", "exit 3"]).exec());
+        assert_eq!(res, (String::new(), 3));
+    }
+
+    pub fn harness_sees_failed_exec() {
+        let res = run_in_child(|| exec::Command::new("/nonexistent/program").exec());
+        assert_eq!(res, (String::new(), 127));
+    }
+
+    /// Make a fresh directory, with an empty `inner` subdirectory, for a test
+    /// to use as its root or current directory.
+    fn new_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("exec-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("inner")).unwrap();
+        dir
+    }
+
+    /// Print the current directory and user ID from a `pre_exec` closure.
+    /// Nothing can be run inside the empty root, so the exec itself fails
+    /// afterwards.
+    fn report_from_pre_exec(cmd: &mut exec::Command) -> &mut exec::Command {
+        unsafe {
+            cmd.pre_exec(|| {
+                let mut stdout = std::io::stdout();
+                write!(
+                    stdout,
+                    "{} {}",
+                    env::current_dir()?.display(),
+                    libc::getuid()
+                )?;
+                stdout.flush()
+            })
+        }
+    }
+
+    pub fn chdir_happens_inside_chroot() {
+        let root = new_dir("chroot-chdir");
+        let res = run_in_child(|| {
+            let mut cmd = exec::Command::new("/bin/true");
+            cmd.current_dir("/inner").chroot(&root);
+            report_from_pre_exec(&mut cmd).exec()
+        });
+        assert_eq!(res, ("/inner 0".to_owned(), 127));
+    }
+
+    pub fn privileges_are_dropped_after_chroot() {
+        let root = new_dir("chroot-uid");
+        let res = run_in_child(|| {
+            let mut cmd = exec::Command::new("/bin/true");
+            // Set the user first, to show the order we call these in doesn't
+            // matter: `chroot` would fail after dropping root.
+            cmd.groups(&[]).gid(65534).uid(65534).chroot(&root);
+            report_from_pre_exec(&mut cmd).exec()
+        });
+        assert_eq!(res, ("/ 65534".to_owned(), 127));
+    }
+
+    pub fn streams_are_connected_before_chroot() {
+        let root = new_dir("chroot-stdio");
+        let out = env::temp_dir().join(format!("exec-chroot-stdio-{}.out", process::id()));
+        let res = run_in_child(|| {
+            let mut cmd = exec::Command::new("/bin/true");
+            cmd.chroot(&root).stdout(exec::Stdio::Path(out.clone()));
+            report_from_pre_exec(&mut cmd).exec()
+        });
+        assert_eq!(res, (String::new(), 127));
+        assert_eq!(fs::read_to_string(&out).unwrap(), "/ 0");
+    }
+
+    pub fn streams_are_connected_before_chdir() {
+        let dir = new_dir("chdir-stdio");
+        // Cargo runs tests from the package's directory, so this is opened
+        // from there rather than from `dir`.
+        let res = run_in_child(|| {
+            exec::Command::new("head")
+                .args(["-n", "1"])
+                .current_dir(&dir)
+                .stdin(exec::Stdio::Path("Cargo.toml".into()))
+                .exec()
+        });
+        assert_eq!(res, ("[package]\n".to_owned(), 0));
+    }
+
+    pub fn spawned_streams_are_connected_before_chdir() {
+        let dir = new_dir("spawn-chdir-stdio");
+        let res = run_in_child(|| {
+            exec::Command::new("head")
+                .args(["-n", "1"])
+                .current_dir(&dir)
+                .stdin(exec::Stdio::Path("Cargo.toml".into()))
+                .spawn_replace()
+        });
+        assert_eq!(res, ("[package]\n".to_owned(), 0));
+    }
+}